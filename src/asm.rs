@@ -0,0 +1,486 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::{encode, Cell, Instruction, IntcodeError, Opcode, Parameter};
+
+// Assembler for Intcode.
+//
+// Each line holds optional labels (`name:`), then an instruction or a `db`
+// directive. Operands are `[x]` (position), `#x` (immediate) or `[r+x]`
+// (relative), where `x` is a number or a label, plus or minus numbers.
+// Comments start with `;`. A line can also start with its address (`0012:`),
+// like the disassembler's output; it is checked but has no other effect.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    WrongOperandCount { expected: usize, found: usize },
+    InvalidOperand(String),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    AddressMismatch { expected: usize, actual: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    // 1-based
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "Unknown mnemonic {:?}", m),
+            AsmErrorKind::WrongOperandCount { expected, found } => write!(
+                f,
+                "Expected {} operands, found {}",
+                expected, found,
+            ),
+            AsmErrorKind::InvalidOperand(o) => write!(f, "Invalid operand {:?}", o),
+            AsmErrorKind::InvalidLabel(l) => write!(f, "Invalid label {:?}", l),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "Label {:?} already defined", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "Undefined label {:?}", l),
+            AsmErrorKind::AddressMismatch { expected, actual } => write!(
+                f,
+                "Line is marked as address {} but is at {}",
+                expected, actual,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// A number or label, plus or minus some numbers
+#[derive(Clone, Debug)]
+struct Expr {
+    label: Option<String>,
+    offset: i64,
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Position(Expr),
+    Immediate(Expr),
+    Relative(Expr),
+}
+
+impl Operand {
+    fn expr(&self) -> &Expr {
+        match self {
+            Operand::Position(e) | Operand::Immediate(e) | Operand::Relative(e) => e,
+        }
+    }
+
+    // With the label resolved
+    fn parameter(&self, value: i64) -> Parameter {
+        match self {
+            Operand::Position(_) => Parameter::Position(value),
+            Operand::Immediate(_) => Parameter::Immediate(value),
+            Operand::Relative(_) => Parameter::Relative(value),
+        }
+    }
+}
+
+enum Item {
+    Instruction(Opcode, Vec<Operand>),
+    Data(Vec<Expr>),
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_expr(text: &str) -> Option<Expr> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if text.is_empty() {
+        return None;
+    }
+    let mut expr = Expr {
+        label: None,
+        offset: 0,
+    };
+    // Split into signed terms
+    let mut terms = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if (c == '+' || c == '-') && i > 0 {
+            terms.push(&text[start..i]);
+            start = i;
+        }
+    }
+    terms.push(&text[start..]);
+    for term in terms {
+        let (negative, name) = match term.as_bytes()[0] {
+            b'+' => (false, &term[1..]),
+            b'-' => (true, &term[1..]),
+            _ => (false, term),
+        };
+        if let Ok(number) = name.parse::<i64>() {
+            expr.offset = if negative {
+                expr.offset.checked_sub(number)?
+            } else {
+                expr.offset.checked_add(number)?
+            };
+        } else if is_identifier(name) && !negative && expr.label.is_none() {
+            expr.label = Some(name.to_owned());
+        } else {
+            return None;
+        }
+    }
+    Some(expr)
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix('#') {
+        parse_expr(rest).map(Operand::Immediate)
+    } else if text.starts_with('[') && text.ends_with(']') {
+        let inner = text[1..text.len() - 1].trim();
+        match inner.strip_prefix('r') {
+            Some("") => Some(Operand::Relative(Expr {
+                label: None,
+                offset: 0,
+            })),
+            Some(rest) if rest.trim_start().starts_with(['+', '-']) => {
+                parse_expr(rest).map(Operand::Relative)
+            }
+            _ => parse_expr(inner).map(Operand::Position),
+        }
+    } else {
+        parse_expr(text).map(Operand::Position)
+    }
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(|s| s.trim()).collect()
+    }
+}
+
+// Where an address comes from in the source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    // Closest label at or before the address, and the offset from it
+    pub label: Option<(&'a str, usize)>,
+}
+
+impl fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        match self.label {
+            Some((label, 0)) => write!(f, " ({})", label),
+            Some((label, offset)) => write!(f, " ({}+{})", label, offset),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    pub file: String,
+    // Start address of each instruction or data line -> line number
+    lines: BTreeMap<usize, usize>,
+    // Address -> first label defined there
+    labels: BTreeMap<usize, String>,
+    // Every label -> its address, including aliases
+    addresses: HashMap<String, usize>,
+    len: usize,
+}
+
+impl SourceMap {
+    pub fn lookup(&self, address: usize) -> Option<SourceLocation<'_>> {
+        if address >= self.len {
+            return None;
+        }
+        let (_, &line) = self.lines.range(..=address).next_back()?;
+        let label = self
+            .labels
+            .range(..=address)
+            .next_back()
+            .map(|(&start, label)| (label.as_str(), address - start));
+        Some(SourceLocation {
+            file: &self.file,
+            line,
+            label,
+        })
+    }
+
+    // Address of a label
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.addresses.get(name).cloned()
+    }
+
+    // Error message, with the source location of the failing instruction
+    pub fn explain<C: Cell>(&self, error: &IntcodeError<C>) -> String {
+        match error.counter().and_then(|counter| self.lookup(counter)) {
+            Some(location) => format!("{}, at {}", error, location),
+            None => error.to_string(),
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    assemble_with_map(source, "").map(|(memory, _)| memory)
+}
+
+pub fn assemble_with_map(source: &str, file: &str) -> Result<(Vec<i64>, SourceMap), AsmError> {
+    // First pass: parse, find label addresses
+    let mut labels = HashMap::new();
+    let mut map_labels = BTreeMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+    for (line_num, line) in source.lines().enumerate() {
+        let line_num = line_num + 1;
+        let error = |kind| AsmError { line: line_num, kind };
+        let mut line = match line.find(';') {
+            Some(pos) => &line[..pos],
+            None => line,
+        }
+        .trim();
+
+        // Labels and address markers
+        while let Some(pos) = line.find(':') {
+            let label = line[..pos].trim();
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(error(AsmErrorKind::AddressMismatch {
+                        expected,
+                        actual: address,
+                    }));
+                }
+            } else if !is_identifier(label) {
+                return Err(error(AsmErrorKind::InvalidLabel(label.to_owned())));
+            } else if labels.insert(label.to_owned(), address).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_owned())));
+            } else {
+                map_labels.entry(address).or_insert_with(|| label.to_owned());
+            }
+            line = line[pos + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(pos) => (&line[..pos], &line[pos..]),
+            None => (line, ""),
+        };
+        let operands = split_operands(rest);
+        let item = if mnemonic.eq_ignore_ascii_case("db") {
+            let mut words = Vec::new();
+            for operand in operands {
+                match parse_expr(operand) {
+                    Some(e) => words.push(e),
+                    None => {
+                        return Err(error(AsmErrorKind::InvalidOperand(operand.to_owned())));
+                    }
+                }
+            }
+            address += words.len();
+            Item::Data(words)
+        } else {
+            let opcode = Opcode::from_mnemonic(mnemonic).ok_or_else(|| {
+                error(AsmErrorKind::UnknownMnemonic(mnemonic.to_owned()))
+            })?;
+            let nb_operands = opcode.parameter_count();
+            if operands.len() != nb_operands {
+                return Err(error(AsmErrorKind::WrongOperandCount {
+                    expected: nb_operands,
+                    found: operands.len(),
+                }));
+            }
+            let mut parsed = Vec::with_capacity(nb_operands);
+            for operand in operands {
+                match parse_operand(operand) {
+                    Some(o) => parsed.push(o),
+                    None => {
+                        return Err(error(AsmErrorKind::InvalidOperand(operand.to_owned())));
+                    }
+                }
+            }
+            address += 1 + nb_operands;
+            Item::Instruction(opcode, parsed)
+        };
+        items.push((line_num, item));
+    }
+
+    // Second pass: encode, resolving labels
+    let mut memory = Vec::with_capacity(address);
+    let mut lines = BTreeMap::new();
+    for (line_num, item) in items {
+        lines.insert(memory.len(), line_num);
+        let resolve = |expr: &Expr| -> Result<i64, AsmError> {
+            match &expr.label {
+                None => Ok(expr.offset),
+                Some(label) => match labels.get(label) {
+                    Some(&addr) => (addr as i64).checked_add(expr.offset).ok_or_else(|| {
+                        let operand = format!("{}{:+}", label, expr.offset);
+                        AsmError { line: line_num, kind: AsmErrorKind::InvalidOperand(operand) }
+                    }),
+                    None => Err(AsmError {
+                        line: line_num,
+                        kind: AsmErrorKind::UndefinedLabel(label.clone()),
+                    }),
+                },
+            }
+        };
+        match item {
+            Item::Instruction(opcode, operands) => {
+                let mut parameters = Vec::with_capacity(operands.len());
+                for operand in &operands {
+                    parameters.push(operand.parameter(resolve(operand.expr())?));
+                }
+                // The operand count was checked in the first pass
+                let instruction = Instruction::new(opcode, &parameters).unwrap();
+                memory.extend(encode(&instruction));
+            }
+            Item::Data(words) => {
+                for word in &words {
+                    memory.push(resolve(word)?);
+                }
+            }
+        }
+    }
+
+    let map = SourceMap {
+        file: file.to_owned(),
+        lines,
+        labels: map_labels,
+        addresses: labels,
+        len: memory.len(),
+    };
+    Ok((memory, map))
+}
+
+#[test]
+fn test_assemble() {
+    // From day 5: is the input equal to 8?
+    let source = "
+        in [value]          ; read input
+        eq [value], [eight], [value]
+        out [value]
+        hlt
+    value: db -1
+    eight: db 8
+    ";
+    assert_eq!(assemble(source).unwrap(), vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+
+    // Relative mode, label arithmetic, immediate labels
+    let source = "
+    start:
+        arb #table
+        add [r+1], #-2, [r-1]
+        JT #1, #start+2
+    table: DB 0, 1, table
+    ";
+    assert_eq!(
+        assemble(source).unwrap(),
+        vec![109, 9, 21201, 1, -2, -1, 1105, 1, 2, 0, 1, 9],
+    );
+
+    // Errors
+    let err = |source| assemble(source).unwrap_err();
+    assert_eq!(
+        err("hlt\nfoo #1"),
+        AsmError { line: 2, kind: AsmErrorKind::UnknownMnemonic("foo".into()) },
+    );
+    assert_eq!(
+        err("out #1, #2"),
+        AsmError {
+            line: 1,
+            kind: AsmErrorKind::WrongOperandCount { expected: 1, found: 2 },
+        },
+    );
+    assert_eq!(
+        err("\n\nout [nowhere]"),
+        AsmError { line: 3, kind: AsmErrorKind::UndefinedLabel("nowhere".into()) },
+    );
+    assert_eq!(
+        err("a: hlt\na: hlt"),
+        AsmError { line: 2, kind: AsmErrorKind::DuplicateLabel("a".into()) },
+    );
+    assert_eq!(
+        err("out [1+]"),
+        AsmError { line: 1, kind: AsmErrorKind::InvalidOperand("[1+]".into()) },
+    );
+    assert_eq!(
+        err("hlt\na: out [a+9223372036854775807]"),
+        AsmError {
+            line: 2,
+            kind: AsmErrorKind::InvalidOperand("a+9223372036854775807".into()),
+        },
+    );
+    assert_eq!(
+        err("hlt\n0002: hlt"),
+        AsmError {
+            line: 2,
+            kind: AsmErrorKind::AddressMismatch { expected: 2, actual: 1 },
+        },
+    );
+}
+
+#[test]
+fn test_round_trip() {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use crate::{Memory, Program};
+    use crate::disasm::disassemble;
+
+    for day in &["02", "05", "07", "09", "11"] {
+        let file = File::open(format!("inputs/day{}.txt", day)).unwrap();
+        let program = Program::from_reader(BufReader::new(file)).unwrap();
+        let listing: Vec<String> = disassemble(&program.memory)
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let memory = assemble(&listing.join("\n")).unwrap();
+        assert_eq!(memory, program.memory.to_vec());
+    }
+}
+
+#[test]
+fn test_source_map() {
+    use crate::{Program, no_input, no_output};
+
+    let source = "\
+; Count down, then crash
+start:
+    add [count], #-1, [count]
+    jt [count], #start
+broken:
+    db 42
+count: db 3
+";
+    let (memory, map) = assemble_with_map(source, "countdown.asm").unwrap();
+    assert_eq!(map.label("count"), Some(8));
+
+    // Aliases can be looked up too, the first one is shown
+    let (_, alias) = assemble_with_map("a: b: hlt", "alias.s").unwrap();
+    assert_eq!((alias.label("a"), alias.label("b")), (Some(0), Some(0)));
+    assert_eq!(alias.lookup(0).unwrap().to_string(), "alias.s:1 (a)");
+    assert_eq!(map.lookup(0).unwrap().to_string(), "countdown.asm:3 (start)");
+    assert_eq!(map.lookup(5).unwrap().to_string(), "countdown.asm:4 (start+5)");
+    assert_eq!(map.lookup(8).unwrap().line, 7);
+    assert_eq!(map.lookup(9), None);
+
+    let mut program = Program::new(memory);
+    let err = program.run(no_input, no_output).unwrap_err();
+    assert_eq!(
+        map.explain(&err),
+        "Invalid opcode 42 at position 7, at countdown.asm:6 (broken)",
+    );
+}
@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::Cell;
+
+// Arbitrary-precision integer, as a sign and a magnitude in base 2^32
+// (least significant limb first, no trailing zero limbs)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = carry
+            + *a.get(i).unwrap_or(&0) as u64
+            + *b.get(i).unwrap_or(&0) as u64;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// a - b, with |a| >= |b|
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = limb as i64 - borrow - *b.get(i).unwrap_or(&0) as i64;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result.push(diff as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = result[i + j] as u64 + x as u64 * y as u64 + carry;
+            result[i + j] = cur as u32;
+            carry = cur >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        trim(&mut limbs);
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            BigInt::from_parts(self.negative, add_magnitude(&self.limbs, &other.limbs))
+        } else if cmp_magnitude(&self.limbs, &other.limbs) != Ordering::Less {
+            BigInt::from_parts(self.negative, sub_magnitude(&self.limbs, &other.limbs))
+        } else {
+            BigInt::from_parts(other.negative, sub_magnitude(&other.limbs, &self.limbs))
+        }
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != other.negative,
+            mul_magnitude(&self.limbs, &other.limbs),
+        )
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+
+    // Divide the magnitude by a small number in place, returning the remainder
+    fn div_rem_small(limbs: &mut Vec<u32>, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for limb in limbs.iter_mut().rev() {
+            let cur = (rem << 32) | *limb as u64;
+            *limb = (cur / divisor as u64) as u32;
+            rem = cur % divisor as u64;
+        }
+        trim(limbs);
+        rem as u32
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitude(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.limbs.is_empty() {
+            return write!(f, "0");
+        }
+        // Get groups of 9 decimal digits, least significant first
+        let mut groups = Vec::new();
+        let mut limbs = self.limbs.clone();
+        while !limbs.is_empty() {
+            groups.push(BigInt::div_rem_small(&mut limbs, 1_000_000_000));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", groups.pop().unwrap())?;
+        for group in groups.iter().rev() {
+            write!(f, "{:09}", group)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> BigInt {
+        let magnitude = value.unsigned_abs();
+        BigInt::from_parts(
+            value < 0,
+            vec![magnitude as u32, (magnitude >> 32) as u32],
+        )
+    }
+}
+
+impl Cell for BigInt {
+    fn from_i64(value: i64) -> BigInt {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let mut magnitude = 0u64;
+        for (i, &limb) in self.limbs.iter().enumerate() {
+            magnitude |= (limb as u64) << (32 * i);
+        }
+        if self.negative {
+            if magnitude <= 1 << 63 {
+                Some((magnitude as i64).wrapping_neg())
+            } else {
+                None
+            }
+        } else if magnitude < 1 << 63 {
+            Some(magnitude as i64)
+        } else {
+            None
+        }
+    }
+
+    fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    // Never overflows
+    fn checked_add(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self.add(other))
+    }
+
+    fn wrapping_add(&self, other: &BigInt) -> BigInt {
+        self.add(other)
+    }
+
+    fn saturating_add(&self, other: &BigInt) -> BigInt {
+        self.add(other)
+    }
+
+    fn checked_mul(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self.mul(other))
+    }
+
+    fn wrapping_mul(&self, other: &BigInt) -> BigInt {
+        self.mul(other)
+    }
+
+    fn saturating_mul(&self, other: &BigInt) -> BigInt {
+        self.mul(other)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+}
+
+#[test]
+fn test_bigint() {
+    let a = BigInt::from(1_000_000_007);
+    let b = BigInt::from(-999_999_999_999);
+    assert_eq!(a.add(&b).to_string(), "-998999999992");
+    assert_eq!(b.add(&a).to_i64(), Some(-998_999_999_992));
+    assert_eq!(a.mul(&b).to_string(), "-1000000006998999999993");
+    assert_eq!(b.mul(&b).mul(&b).to_string(), "-999999999997000000000002999999999999");
+    assert_eq!(a.add(&a.neg()), BigInt::zero());
+    assert!(b < a);
+    assert!(b.mul(&b) > a);
+    assert!(b.mul(&a) < b);
+    assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+    assert_eq!(BigInt::from(i64::MAX).add(&BigInt::from(1)).to_i64(), None);
+    assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+}
@@ -0,0 +1,206 @@
+use std::io::{self, Read, Write};
+
+use crate::{read_program, Cell, IntcodeError};
+
+// Binary program format, version 1:
+//
+//   magic     4 bytes, "\0ICB" (the NUL can't start a text program)
+//   version   1 byte, 1
+//   flags     1 byte, bit 0 set if there is a checksum
+//   count     varint, number of cells
+//   cells     zig-zag varints
+//   checksum  4 bytes little-endian, FNV-1a of the cell bytes, if flagged
+//
+// Varints are LEB128: 7 bits per byte, least significant first, high bit set
+// on all but the last byte. Zig-zag maps 0, -1, 1, -2... to 0, 1, 2, 3...
+//
+// Cells are 64-bit. Wider cell types can be written as long as their values
+// fit, and read back as is.
+pub const BINARY_MAGIC: &[u8; 4] = b"\0ICB";
+pub const BINARY_VERSION: u8 = 1;
+const FLAG_CHECKSUM: u8 = 1;
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    let mut hash = 0x811c_9dc5u32;
+    for &byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+pub fn write_binary<C: Cell, W: Write>(mut out: W, memory: &[C], checksum: bool) -> io::Result<()> {
+    let mut header = BINARY_MAGIC.to_vec();
+    header.push(BINARY_VERSION);
+    header.push(if checksum { FLAG_CHECKSUM } else { 0 });
+    write_varint(&mut header, memory.len() as u64);
+
+    let mut cells = Vec::with_capacity(memory.len() * 2);
+    for value in memory {
+        let value = value.to_i64().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} doesn't fit in the binary format", value),
+            )
+        })?;
+        write_varint(&mut cells, zigzag(value));
+    }
+
+    out.write_all(&header)?;
+    out.write_all(&cells)?;
+    if checksum {
+        out.write_all(&fnv1a(&cells).to_le_bytes())?;
+    }
+    out.flush()
+}
+
+// Decodes from a buffer, keeping track of the position for errors
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn error<C>(&self, message: &'static str) -> IntcodeError<C> {
+        IntcodeError::InvalidBinary {
+            position: self.position,
+            message,
+        }
+    }
+
+    fn take<C>(&mut self, len: usize) -> Result<&[u8], IntcodeError<C>> {
+        if self.bytes.len() - self.position < len {
+            return Err(self.error("unexpected end of file"));
+        }
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn varint<C>(&mut self) -> Result<u64, IntcodeError<C>> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            // The 10th byte only has 1 bit left to give
+            if shift == 63 && byte > 1 {
+                return Err(self.error("varint too large"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error("varint too large"))
+    }
+}
+
+pub fn read_binary<C: Cell, R: Read>(mut file: R) -> Result<Vec<C>, IntcodeError<C>> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let mut decoder = Decoder { bytes: &bytes, position: 0 };
+
+    if decoder.take(4)? != BINARY_MAGIC {
+        return Err(decoder.error("not a binary program"));
+    }
+    if decoder.take(1)?[0] != BINARY_VERSION {
+        return Err(decoder.error("unsupported version"));
+    }
+    let flags = decoder.take(1)?[0];
+    if flags & !FLAG_CHECKSUM != 0 {
+        return Err(decoder.error("unknown flags"));
+    }
+    let count = decoder.varint()?;
+
+    let start = decoder.position;
+    // Each cell is at least one byte, don't trust the count more than that
+    let mut memory = Vec::with_capacity((count as usize).min(bytes.len()));
+    for _ in 0..count {
+        memory.push(C::from_i64(unzigzag(decoder.varint()?)));
+    }
+    let end = decoder.position;
+
+    if flags & FLAG_CHECKSUM != 0 {
+        let mut checksum = [0u8; 4];
+        checksum.copy_from_slice(decoder.take(4)?);
+        if u32::from_le_bytes(checksum) != fnv1a(&bytes[start..end]) {
+            return Err(decoder.error("checksum mismatch"));
+        }
+    }
+    if decoder.position != bytes.len() {
+        return Err(decoder.error("trailing data"));
+    }
+    Ok(memory)
+}
+
+// Read either format, telling them apart from the first byte
+pub fn read_any<C: Cell, R: Read>(mut file: R) -> Result<Vec<C>, IntcodeError<C>> {
+    let mut first = [0u8];
+    let len = file.read(&mut first)?;
+    let file = (&first[..len]).chain(file);
+    if len == 1 && first[0] == BINARY_MAGIC[0] {
+        read_binary(file)
+    } else {
+        read_program(file)
+    }
+}
+
+#[test]
+fn test_binary() {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use crate::{Memory, Program};
+
+    let memory = vec![0, -1, 1, 63, -64, 64, 1_000_000, i64::MIN, i64::MAX];
+    let mut bytes = Vec::new();
+    write_binary(&mut bytes, &memory, false).unwrap();
+    assert_eq!(&bytes[..7], b"\0ICB\x01\x00\x09");
+    assert_eq!(&bytes[7..13], &[0, 1, 2, 126, 127, 128]);
+    assert_eq!(read_binary::<i64, _>(&bytes[..]).unwrap(), memory);
+
+    // Checksums catch corruption
+    let mut bytes = Vec::new();
+    write_binary(&mut bytes, &memory, true).unwrap();
+    assert_eq!(read_binary::<i64, _>(&bytes[..]).unwrap(), memory);
+    bytes[8] ^= 4;
+    let error = |bytes: &[u8]| match read_binary::<i64, _>(bytes) {
+        Err(IntcodeError::InvalidBinary { position, message }) => (position, message),
+        r => panic!("Unexpected result: {:?}", r),
+    };
+    assert_eq!(error(&bytes).1, "checksum mismatch");
+    assert_eq!(error(&bytes[..10]), (10, "unexpected end of file"));
+    assert_eq!(error(b"\0ICB\x02"), (5, "unsupported version"));
+    let too_large = b"\0ICB\x01\x00\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f";
+    assert_eq!(error(too_large), (17, "varint too large"));
+
+    // Programs load from either format
+    let file = BufReader::new(File::open("inputs/day09.txt").unwrap());
+    let text = Program::from_reader(file).unwrap();
+    let mut bytes = Vec::new();
+    write_binary(&mut bytes, &text.memory.to_vec(), true).unwrap();
+    let binary = Program::from_reader(&bytes[..]).unwrap();
+    assert_eq!(binary.memory.to_vec(), text.memory.to_vec());
+    assert_eq!(Program::from_reader(&b"1,-2,3\n"[..]).unwrap().memory.to_vec(), vec![1, -2, 3]);
+
+    // Other cell types, as long as the values fit
+    let wide: Program<i128> = Program::read_cells(&bytes[..]).unwrap();
+    assert_eq!(wide.memory.read(0), text.memory.read(0) as i128);
+    let mut bytes = Vec::new();
+    assert!(write_binary(&mut bytes, &[1i128 << 64], false).is_err());
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{Cell, DenseMemory, Memory, Opcode, Program};
+
+// Shareable between threads, like the rest of the machine
+type Condition<C, M> = Arc<dyn Fn(&Program<C, M>) -> bool + Send + Sync>;
+
+// Where `Program::resume()` should stop, before executing the instruction
+#[derive(Clone)]
+pub enum Breakpoint<C: Cell = i64, M: Memory<C> = DenseMemory<C>> {
+    // The counter reaches this address
+    Address(usize),
+    // The next instruction has this opcode, whatever the parameter modes
+    Opcode(Opcode),
+    // The condition holds, e.g. `|p| p.memory.read(100) == 16`
+    Condition(Condition<C, M>),
+}
+
+impl<C: Cell, M: Memory<C>> Breakpoint<C, M> {
+    pub fn when<F>(condition: F) -> Breakpoint<C, M>
+    where
+        F: Fn(&Program<C, M>) -> bool + Send + Sync + 'static,
+    {
+        Breakpoint::Condition(Arc::new(condition))
+    }
+
+    pub(crate) fn matches(&self, program: &Program<C, M>) -> bool {
+        match self {
+            Breakpoint::Address(addr) => program.counter == *addr,
+            Breakpoint::Opcode(opcode) => {
+                let value = program.memory.read(program.counter);
+                value.to_i64().map(|v| v % 100) == Some(opcode.code())
+            }
+            Breakpoint::Condition(condition) => condition(program),
+        }
+    }
+}
+
+impl<C: Cell, M: Memory<C>> fmt::Debug for Breakpoint<C, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(addr) => write!(f, "Address({})", addr),
+            Breakpoint::Opcode(opcode) => write!(f, "Opcode({})", opcode),
+            Breakpoint::Condition(_) => write!(f, "Condition(..)"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+// Stop after an instruction reads or writes in a range of addresses. Only
+// the operands count, not fetching the instruction itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub fn new(range: Range<usize>, access: Access) -> Watchpoint {
+        Watchpoint { range, access }
+    }
+
+    pub(crate) fn matches(&self, addr: usize, access: Access) -> bool {
+        self.range.contains(&addr) && (self.access == access || self.access == Access::ReadWrite)
+    }
+}
+
+#[test]
+fn test_breakpoints() {
+    use crate::{StopReason, no_input, no_output};
+
+    // Is the input equal to 8?
+    let mut program = Program::new(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+    let eq = program.add_breakpoint(Breakpoint::Opcode(Opcode::Equals));
+    let out = program.add_breakpoint(Breakpoint::Address(6));
+    let watch = program.add_watchpoint(Watchpoint::new(9..10, Access::Write));
+    program.push_input(8);
+
+    let write = StopReason::Watchpoint { id: watch, address: 9, access: Access::Write };
+    assert_eq!(program.resume().unwrap(), write);
+    assert_eq!((program.counter, program.memory.read(9)), (2, 8));
+    assert_eq!(program.resume().unwrap(), StopReason::Breakpoint(eq));
+    assert_eq!(program.counter, 2);
+    assert_eq!(program.resume().unwrap(), write);
+    assert_eq!(program.memory.read(9), 1);
+    assert_eq!(program.resume().unwrap(), StopReason::Breakpoint(out));
+    assert_eq!(program.counter, 6);
+    assert_eq!(program.resume().unwrap(), StopReason::Output(1));
+    assert_eq!(program.resume().unwrap(), StopReason::Halted);
+
+    assert!(program.remove_breakpoint(eq));
+    assert!(program.remove_breakpoint(out));
+    assert!(program.remove_breakpoint(watch));
+    assert!(!program.remove_breakpoint(watch));
+
+    // Conditions and reads
+    program.reset();
+    let cond = program.add_breakpoint(Breakpoint::when(|p: &Program| p.memory.read(9) == 1));
+    let read = program.add_watchpoint(Watchpoint::new(10..11, Access::ReadWrite));
+    program.push_input(8);
+    assert_eq!(
+        program.resume().unwrap(),
+        StopReason::Watchpoint { id: read, address: 10, access: Access::Read },
+    );
+    assert_eq!(program.resume().unwrap(), StopReason::Breakpoint(cond));
+    assert_eq!(program.counter, 6);
+    assert_eq!(program.resume().unwrap(), StopReason::Output(1));
+
+    // Stepping off a breakpoint doesn't skip the next one
+    let mut program = Program::new(vec![1101, 1, 1, 9, 1101, 2, 2, 9, 99, 0]);
+    let first = program.add_breakpoint(Breakpoint::Address(0));
+    let second = program.add_breakpoint(Breakpoint::Address(4));
+    assert_eq!(program.resume().unwrap(), StopReason::Breakpoint(first));
+    program.step(no_input, no_output).unwrap();
+    assert_eq!(program.counter, 4);
+    assert_eq!(program.resume().unwrap(), StopReason::Breakpoint(second));
+    assert_eq!(program.resume().unwrap(), StopReason::Halted);
+}
@@ -0,0 +1,103 @@
+use std::fmt::{Debug, Display};
+
+// A value in the machine's memory
+pub trait Cell: Clone + Debug + Display + Ord + 'static {
+    fn from_i64(value: i64) -> Self;
+
+    // None if it doesn't fit
+    fn to_i64(&self) -> Option<i64>;
+
+    fn is_negative(&self) -> bool;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    fn zero() -> Self {
+        Self::from_i64(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    // None if it is negative or too large to be an address
+    fn to_usize(&self) -> Option<usize> {
+        if self.is_negative() {
+            None
+        } else {
+            self.to_i64().and_then(|v| {
+                use std::convert::TryFrom;
+                usize::try_from(v).ok()
+            })
+        }
+    }
+}
+
+macro_rules! primitive_cell {
+    ($t:ty) => {
+        impl Cell for $t {
+            #[inline]
+            fn from_i64(value: i64) -> $t {
+                value as $t
+            }
+
+            #[inline]
+            fn to_i64(&self) -> Option<i64> {
+                use std::convert::TryFrom;
+                i64::try_from(*self).ok()
+            }
+
+            #[inline]
+            fn is_negative(&self) -> bool {
+                *self < 0
+            }
+
+            #[inline]
+            fn checked_add(&self, other: &$t) -> Option<$t> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            #[inline]
+            fn wrapping_add(&self, other: &$t) -> $t {
+                <$t>::wrapping_add(*self, *other)
+            }
+
+            #[inline]
+            fn saturating_add(&self, other: &$t) -> $t {
+                <$t>::saturating_add(*self, *other)
+            }
+
+            #[inline]
+            fn checked_mul(&self, other: &$t) -> Option<$t> {
+                <$t>::checked_mul(*self, *other)
+            }
+
+            #[inline]
+            fn wrapping_mul(&self, other: &$t) -> $t {
+                <$t>::wrapping_mul(*self, *other)
+            }
+
+            #[inline]
+            fn saturating_mul(&self, other: &$t) -> $t {
+                <$t>::saturating_mul(*self, *other)
+            }
+
+            #[inline]
+            fn zero() -> $t {
+                0
+            }
+
+            #[inline]
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+        }
+    };
+}
+
+primitive_cell!(i64);
+primitive_cell!(i128);
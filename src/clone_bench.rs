@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Instant;
+
+use adventofcode2019::{
+    Res, Program, Memory, DenseMemory, PagedMemory, SparseMemory, read_program, no_input,
+    no_output,
+};
+
+// Day 2's noun/verb search, cloning the machine for each attempt
+fn search<M: Memory + Clone>(program: &Program<i64, M>) -> Option<i64> {
+    let mut answer = None;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut program = program.clone();
+            program.memory.write(1, noun);
+            program.memory.write(2, verb);
+            if program.run(no_input, no_output).is_ok()
+                && program.memory.read(0) == 19690720
+            {
+                answer = Some(100 * noun + verb);
+            }
+        }
+    }
+    answer
+}
+
+fn bench<M: Memory + Clone>(name: &str, program: &Program<i64, M>, rounds: u32) {
+    let start = Instant::now();
+    let mut answer = None;
+    for _ in 0..rounds {
+        answer = search(program);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:>6}: {:?} per search ({:?})",
+        name, elapsed / rounds, answer,
+    );
+}
+
+fn main() -> Res<()> {
+    // Read the program
+    let file = BufReader::new(File::open("inputs/day02.txt")?);
+    let memory = read_program(file)?;
+
+    let rounds = 20;
+    bench("dense", &Program::with_memory(memory.clone(), DenseMemory::new()), rounds);
+    bench("paged", &Program::with_memory(memory.clone(), PagedMemory::new()), rounds);
+    bench("sparse", &Program::with_memory(memory, SparseMemory::new()), rounds);
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::disasm::{disassemble_one, Line};
+use crate::trace::{install, install_from_env};
+use crate::{Cell, Memory, Program, TraceEvent, Tracer};
+
+fn mark(flags: &mut Vec<bool>, addr: usize) {
+    if addr >= flags.len() {
+        flags.resize(addr + 1, false);
+    }
+    flags[addr] = true;
+}
+
+fn is_set(flags: &[bool], addr: usize) -> bool {
+    flags.get(addr).cloned().unwrap_or(false)
+}
+
+// Which addresses were executed as instructions (opcode and parameters), read
+// as operands, or written to
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    starts: Vec<bool>,
+    executed: Vec<bool>,
+    read: Vec<bool>,
+    written: Vec<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoverageSummary {
+    pub total: usize,
+    pub executed: usize,
+    pub read: usize,
+    pub written: usize,
+}
+
+impl fmt::Display for CoverageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |n: usize| 100.0 * n as f64 / self.total.max(1) as f64;
+        write!(
+            f,
+            "Executed {}/{} ({:.1}%), read {} ({:.1}%), written {} ({:.1}%)",
+            self.executed, self.total, percent(self.executed),
+            self.read, percent(self.read),
+            self.written, percent(self.written),
+        )
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Default::default()
+    }
+
+    pub fn install<C: Cell, M: Memory<C>>(program: &mut Program<C, M>) -> Arc<Mutex<Coverage>> {
+        install(program)
+    }
+
+    pub fn is_executed(&self, addr: usize) -> bool {
+        is_set(&self.executed, addr)
+    }
+
+    pub fn is_read(&self, addr: usize) -> bool {
+        is_set(&self.read, addr)
+    }
+
+    pub fn is_written(&self, addr: usize) -> bool {
+        is_set(&self.written, addr)
+    }
+
+    // Counts over the first `len` addresses, usually the program's size
+    pub fn summary(&self, len: usize) -> CoverageSummary {
+        let count = |flags: &[bool]| flags.iter().take(len).filter(|&&f| f).count();
+        CoverageSummary {
+            total: len,
+            executed: count(&self.executed),
+            read: count(&self.read),
+            written: count(&self.written),
+        }
+    }
+
+    // Listing of the memory, with `X`, `R` and `W` columns for executed, read
+    // and written. Instructions are decoded where they were executed; the
+    // rest is shown as data.
+    pub fn listing<C: Cell, M: Memory<C> + ?Sized>(&self, memory: &M) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut address = 0;
+        while address < memory.len() {
+            let flag = |set: bool, c: char| if set { c } else { '-' };
+            let marks: String = [
+                flag(self.is_executed(address), 'X'),
+                flag(self.is_read(address), 'R'),
+                flag(self.is_written(address), 'W'),
+            ]
+            .iter()
+            .collect();
+            let line = if is_set(&self.starts, address) {
+                disassemble_one(memory, address)
+            } else {
+                Line {
+                    address,
+                    length: 1,
+                    text: format!("DB {}", memory.read(address)),
+                }
+            };
+            lines.push(format!("{} {}", marks, line));
+            address += line.length;
+        }
+        lines
+    }
+}
+
+impl<C: Cell> Tracer<C> for Coverage {
+    fn event(&mut self, event: &TraceEvent<C>) {
+        match event {
+            TraceEvent::Instruction { counter, instr, .. } => {
+                mark(&mut self.starts, *counter);
+                let nb_params = instr.parameter_count();
+                for addr in *counter..=*counter + nb_params {
+                    mark(&mut self.executed, addr);
+                }
+            }
+            TraceEvent::Operand { address: Some(addr), .. } => mark(&mut self.read, *addr),
+            TraceEvent::Write { address, .. } => mark(&mut self.written, *address),
+            _ => {}
+        }
+    }
+}
+
+// Set to record coverage in the day binaries
+pub const COVERAGE_ENV: &str = "INTCODE_COVERAGE";
+
+pub fn coverage_from_env<C, M>(program: &mut Program<C, M>) -> Option<Arc<Mutex<Coverage>>>
+where
+    C: Cell,
+    M: Memory<C>,
+{
+    install_from_env(program, COVERAGE_ENV)
+}
+
+#[test]
+fn test_coverage() {
+    // Output 1 if the input is non-zero, else 0
+    let mut program = Program::new(vec![3, 12, 1005, 12, 9, 104, 0, 99, 7, 104, 1, 99, 0]);
+    let coverage = Coverage::install(&mut program);
+    // Alongside another tracer
+    let profiler = crate::Profiler::install(&mut program);
+    program.run(|| Ok(0), |_| Ok(())).unwrap();
+    assert_eq!(profiler.lock().unwrap().instructions, 4);
+    assert_eq!(
+        coverage.lock().unwrap().summary(13),
+        CoverageSummary { total: 13, executed: 8, read: 1, written: 1 },
+    );
+    assert_eq!(
+        coverage.lock().unwrap().listing(&program.memory),
+        vec![
+            "X-- 0000: IN [12]",
+            "X-- 0002: JT [12], #9",
+            "X-- 0005: OUT #0",
+            "X-- 0007: HLT",
+            "--- 0008: DB 7",
+            "--- 0009: DB 104",
+            "--- 0010: DB 1",
+            "--- 0011: DB 99",
+            "-RW 0012: DB 0",
+        ],
+    );
+
+    // The other branch, adding to the same coverage
+    program.reset();
+    program.run(|| Ok(1), |_| Ok(())).unwrap();
+    let summary = coverage.lock().unwrap().summary(13);
+    assert_eq!((summary.executed, summary.read, summary.written), (11, 1, 1));
+    assert_eq!(
+        summary.to_string(),
+        "Executed 11/13 (84.6%), read 1 (7.7%), written 1 (7.7%)",
+    );
+    assert_eq!(coverage.lock().unwrap().listing(&program.memory)[5], "X-- 0009: OUT #1");
+}
@@ -1,29 +1,29 @@
 use std::fs::File;
 use std::io::BufReader;
 
-use adventofcode2019::{Res, Program, no_input, no_output};
+use adventofcode2019::{profiler_from_env, Memory, Res, Program, no_input, no_output};
 
 #[test]
 fn test_exec() {
     let mut program = Program::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
     program.run(no_input, no_output).unwrap();
-    assert_eq!(&program.memory, &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]);
+    assert_eq!(program.memory.to_vec(), [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]);
 
     let mut program = Program::new(vec![1, 0, 0, 0, 99]);
     program.run(no_input, no_output).unwrap();
-    assert_eq!(&program.memory, &[2, 0, 0, 0, 99]);
+    assert_eq!(program.memory.to_vec(), [2, 0, 0, 0, 99]);
 
     let mut program = Program::new(vec![2, 3, 0, 3, 99]);
     program.run(no_input, no_output).unwrap();
-    assert_eq!(&program.memory, &[2, 3, 0, 6, 99]);
+    assert_eq!(program.memory.to_vec(), [2, 3, 0, 6, 99]);
 
     let mut program = Program::new(vec![2, 4, 4, 5, 99, 0]);
     program.run(no_input, no_output).unwrap();
-    assert_eq!(&program.memory, &[2, 4, 4, 5, 99, 9801]);
+    assert_eq!(program.memory.to_vec(), [2, 4, 4, 5, 99, 9801]);
 
     let mut program = Program::new(vec![1, 1, 1, 4, 99, 5, 6, 0, 99]);
     program.run(no_input, no_output).unwrap();
-    assert_eq!(&program.memory, &[30, 1, 1, 4, 2, 5, 6, 0, 99]);
+    assert_eq!(program.memory.to_vec(), [30, 1, 1, 4, 2, 5, 6, 0, 99]);
 }
 
 fn main() -> Res<()> {
@@ -31,21 +31,24 @@ fn main() -> Res<()> {
     let file = BufReader::new(File::open("inputs/day02.txt")?);
 
     // Read the program
-    let program = Program::from_reader(file)?;
+    let mut program = Program::from_reader(file)?;
+
+    // Profile all runs if INTCODE_PROFILE is set
+    let profiler = profiler_from_env(&mut program);
 
     // First part
     {
         let mut program = program.clone();
 
         // Set it up as required
-        program.memory[1] = 12;
-        program.memory[2] = 2;
+        program.memory.write(1, 12);
+        program.memory.write(2, 2);
 
         // Run it
         program.run(no_input, no_output)?;
 
         // Print output
-        println!("Output: {}", program.memory[0]);
+        println!("Output: {}", program.memory.read(0));
     }
 
     // Second part
@@ -54,12 +57,14 @@ fn main() -> Res<()> {
         for noun in 0..99 {
             for verb in 0..99 {
                 let mut program = program.clone();
-                program.memory[1] = noun;
-                program.memory[2] = verb;
+                // Some combinations might loop forever
+                program.limits.max_instructions = Some(100_000);
+                program.memory.write(1, noun);
+                program.memory.write(2, verb);
                 match program.run(no_input, no_output) {
                     Err(_) => {} // Not good
-                    Ok(()) => {
-                        if program.memory[0] == 19690720 {
+                    Ok(_) => {
+                        if program.memory.read(0) == 19690720 {
                             println!(
                                 "noun = {}, verb = {} ; answer = {}",
                                 noun, verb,
@@ -72,5 +77,9 @@ fn main() -> Res<()> {
         }
     }
 
+    if let Some(profiler) = profiler {
+        eprint!("{}", profiler.lock().unwrap().report(&program.memory, 20));
+    }
+
     Ok(())
 }
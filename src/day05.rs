@@ -1,7 +1,7 @@
 use std::fs::File;
 use std::io::BufReader;
 
-use adventofcode2019::{Res, Program};
+use adventofcode2019::{coverage_from_env, json_tracer_from_env, Memory, Res, Program};
 
 #[test]
 fn test_compare() {
@@ -55,30 +55,58 @@ fn main() -> Res<()> {
     let file = BufReader::new(File::open("inputs/day05.txt")?);
 
     // Read the program
-    let program = Program::from_reader(file)?;
+    let mut program = Program::from_reader(file)?;
+
+    // Falling off the end of memory means something went wrong
+    program.strict = true;
+
+    // Trace to a file if INTCODE_TRACE is set, marking where each part starts
+    let tracer = json_tracer_from_env()?;
+    if let Some(tracer) = &tracer {
+        program.add_tracer(tracer.clone());
+    }
+
+    // With INTCODE_COVERAGE, show how much of the program each part reaches
+    let size = program.memory.len();
 
     // Part 1
     {
+        if let Some(tracer) = &tracer {
+            tracer.lock().unwrap().begin_run("diagnostic 1");
+        }
         let mut program = program.clone();
+        let coverage = coverage_from_env(&mut program);
 
         // Run it
-        let mut output = 0;
-        program.run(|| Ok(1), |i| { output = i; Ok(()) })?;
+        program.push_input(1);
+        program.run_buffered()?;
+        let output = program.drain_output().next_back().ok_or("No output")?;
 
         // Print output
         println!("Output for diagnostic 1: {}", output);
+        if let Some(coverage) = coverage {
+            eprintln!("Coverage for diagnostic 1: {}", coverage.lock().unwrap().summary(size));
+        }
     }
 
     // Part 2
     {
+        if let Some(tracer) = &tracer {
+            tracer.lock().unwrap().begin_run("diagnostic 5");
+        }
         let mut program = program.clone();
+        let coverage = coverage_from_env(&mut program);
 
         // Run it
-        let mut output = 0;
-        program.run(|| Ok(5), |i| { output = i; Ok(()) })?;
+        program.push_input(5);
+        program.run_buffered()?;
+        let output = program.drain_output().next_back().ok_or("No output")?;
 
         // Print output
         println!("Output for diagnostic 5: {}", output);
+        if let Some(coverage) = coverage {
+            eprintln!("Coverage for diagnostic 5: {}", coverage.lock().unwrap().summary(size));
+        }
     }
 
     Ok(())
@@ -1,7 +1,7 @@
 use std::fs::File;
 use std::io::BufReader;
 
-use adventofcode2019::{Res, Program};
+use adventofcode2019::{profiler_from_env, Res, Program, StopReason};
 
 struct Permutations {
     size: usize,
@@ -101,7 +101,10 @@ fn main() -> Res<()> {
     let file = BufReader::new(File::open("inputs/day07.txt")?);
 
     // Read the program
-    let program = Program::from_reader(file)?;
+    let mut program = Program::from_reader(file)?;
+
+    // Profile all runs if INTCODE_PROFILE is set
+    let profiler = profiler_from_env(&mut program);
 
     // Part 1
     {
@@ -113,12 +116,9 @@ fn main() -> Res<()> {
             let mut output = 0;
             for phase in &phases {
                 let mut program = program.clone();
-                // Elements are read in reverse: phase then current output
-                let mut inputs = vec![output, *phase as i64];
-                program.run(
-                    || inputs.pop().ok_or("Read too many inputs".into()),
-                    |i| { output = i; Ok(()) },
-                )?;
+                program.extend_input(vec![*phase as i64, output]);
+                program.run_buffered()?;
+                output = program.take_output().ok_or("No output")?;
             }
 
             // Update best phases
@@ -151,33 +151,24 @@ fn main() -> Res<()> {
             // Instantiate 5 programs
             let mut programs: Vec<_> = (0..5).map(|_| program.clone()).collect();
 
+            // Supply the phase settings
+            for (program, phase) in programs.iter_mut().zip(&phases) {
+                program.resume_with_input(*phase as i64)?;
+            }
+
             // Loop until any finishes
-            let mut supplied_phase = false;
             let mut output = 0;
-            let mut running = true;
-            while running {
-                for (program, phase) in programs.iter_mut().zip(&phases) {
-                    let mut inputs = if supplied_phase {
-                        vec![output]
-                    } else {
-                        vec![output, *phase as i64]
-                    };
-
-                    // Run program until it outputs something, or exits
-                    let mut got_output = false;
-                    while running && !got_output {
-                        running = program.step(
-                            || inputs.pop().ok_or("Read too many inputs".into()),
-                            |i| {
-                                output = i;
-                                got_output = true;
-                                Ok(())
-                            },
-                        )?;
+            'feedback: loop {
+                for program in programs.iter_mut() {
+                    match program.resume_with_input(output)? {
+                        StopReason::Output(i) => output = i,
+                        StopReason::Halted => break 'feedback,
+                        StopReason::NeedsInput => {
+                            return Err("Read too many inputs".into());
+                        }
+                        stop => return Err(format!("Unexpected stop: {:?}", stop).into()),
                     }
                 }
-
-                supplied_phase = true;
             }
 
             // Update best phases
@@ -197,5 +188,9 @@ fn main() -> Res<()> {
         println!("Best output: {} for phases {:?}", best_output, best_phases);
     }
 
+    if let Some(profiler) = profiler {
+        eprint!("{}", profiler.lock().unwrap().report(&program.memory, 20));
+    }
+
     Ok(())
 }
@@ -1,7 +1,9 @@
 use std::fs::File;
 use std::io::BufReader;
 
-use adventofcode2019::{Res, Program, no_input, no_output};
+use adventofcode2019::{json_tracer_from_env, Res, Program};
+#[cfg(test)]
+use adventofcode2019::{no_input, no_output};
 
 #[test]
 fn test_change_relative_base() {
@@ -39,20 +41,40 @@ fn main() -> Res<()> {
     // Read the program
     let mut program = Program::from_reader(file)?;
 
+    // Falling off the end of memory means something went wrong
+    program.strict = true;
+
+    // Trace to a file if INTCODE_TRACE is set, marking where each part starts
+    let tracer = json_tracer_from_env()?;
+    if let Some(tracer) = &tracer {
+        program.add_tracer(tracer.clone());
+    }
+
     // Part 1
     {
+        if let Some(tracer) = &tracer {
+            tracer.lock().unwrap().begin_run("part 1");
+        }
         // Run and get the output
-        let mut output = 0;
-        program.run(|| Ok(1), |i| { output = i; Ok(()) })?;
+        program.push_input(1);
+        program.run_buffered()?;
+        let output = program.drain_output().next_back().ok_or("No output")?;
 
         println!("BOOST keycode: {}", output);
     }
 
     // Part 2
     {
+        if let Some(tracer) = &tracer {
+            tracer.lock().unwrap().begin_run("part 2");
+        }
+        // Start over from a fresh machine
+        program.reset();
+
         // Run and get the output
-        let mut output = 0;
-        program.run(|| Ok(2), |i| { output = i; Ok(()) })?;
+        program.push_input(2);
+        program.run_buffered()?;
+        let output = program.drain_output().next_back().ok_or("No output")?;
 
         println!("Coordinates: {}", output);
     }
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 
-use adventofcode2019::{Res, Program};
+use adventofcode2019::{json_tracer_from_env, Res, Program, StopReason};
 
 enum Direction {
     Up,
@@ -47,25 +47,27 @@ fn run_drawing_program(mut program: Program, initial: i64) -> Res<HashMap<(i32,
 
     panels.insert(position, initial);
 
-    let mut running = true;
-    while running {
+    loop {
         // Input: color of the currect panel, which defaults to black
         let input = panels.get(&position).cloned().unwrap_or(0);
 
-        // Run program until it outputs something
-        let mut output = Vec::new();
-        while running && output.len() < 2 {
-            running = program.step(|| Ok(input), |i| { output.push(i); Ok(()) })?;
-        }
-        if !running {
-            break;
-        }
+        // Run program until it outputs color and turn
+        let color = match program.resume_with_input(input)? {
+            StopReason::Output(i) => i,
+            StopReason::Halted => break,
+            StopReason::NeedsInput => return Err("Read too many inputs".into()),
+            stop => return Err(format!("Unexpected stop: {:?}", stop).into()),
+        };
+        let turn = match program.resume()? {
+            StopReason::Output(i) => i,
+            _ => return Err("Expected a turn instruction".into()),
+        };
 
         // Paint current panel
-        panels.insert(position, output[0]);
+        panels.insert(position, color);
 
         // Turn
-        match output[1] {
+        match turn {
             0 => direction.turn_left(),
             1 => direction.turn_right(),
             v => return Err(format!("Invalid turn instruction {}", v).into()),
@@ -86,16 +88,28 @@ fn main() -> Res<()> {
     let file = BufReader::new(File::open("inputs/day11.txt")?);
 
     // Read the program
-    let program = Program::from_reader(file)?;
+    let mut program = Program::from_reader(file)?;
+
+    // Trace to a file if INTCODE_TRACE is set, marking where each part starts
+    let tracer = json_tracer_from_env()?;
+    if let Some(tracer) = &tracer {
+        program.add_tracer(tracer.clone());
+    }
 
     // Part 1
     {
+        if let Some(tracer) = &tracer {
+            tracer.lock().unwrap().begin_run("part 1");
+        }
         let panels = run_drawing_program(program.clone(), 0)?;
         println!("Painted {} panels", panels.len());
     }
 
     // Part 2
     {
+        if let Some(tracer) = &tracer {
+            tracer.lock().unwrap().begin_run("part 2");
+        }
         let panels = run_drawing_program(program.clone(), 1)?;
 
         // Compute size
@@ -124,7 +138,7 @@ fn main() -> Res<()> {
                 };
                 print!("{}", chr);
             }
-            println!("");
+            println!();
         }
     }
 
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::{decode, encode, Cell, Memory};
+
+// One line of the listing: an instruction, or a data word
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub length: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}: {}", self.address, self.text)
+    }
+}
+
+// Decode the instruction at this address, falling back to a data word
+pub fn disassemble_one<C: Cell, M: Memory<C> + ?Sized>(memory: &M, address: usize) -> Line {
+    let code = memory.read(address);
+    let data = Line {
+        address,
+        length: 1,
+        text: format!("DB {}", code),
+    };
+    let instruction = match decode(memory, address) {
+        Ok(instruction) => instruction,
+        Err(_) => return data,
+    };
+    // Parameters past the end, or leftover mode digits, mean this isn't a
+    // real instruction
+    if address + instruction.length() > memory.len() || encode(&instruction)[0] != code {
+        return data;
+    }
+    Line {
+        address,
+        length: instruction.length(),
+        text: instruction.to_string(),
+    }
+}
+
+// Decode the whole memory, in order
+pub fn disassemble<C: Cell, M: Memory<C> + ?Sized>(memory: &M) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < memory.len() {
+        let line = disassemble_one(memory, address);
+        address += line.length;
+        lines.push(line);
+    }
+    lines
+}
+
+#[test]
+fn test_disassemble() {
+    use crate::Program;
+
+    let program = Program::new(vec![
+        109, 19, 1202, -3, 4, 100, 1105, 1, 12, 203, 5, 99, 42, 30101, 1, 2,
+    ]);
+    let listing: Vec<String> = disassemble(&program.memory)
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+    assert_eq!(
+        listing,
+        vec![
+            "0000: ARB #19",
+            "0002: MUL [r-3], #4, [100]",
+            "0006: JT #1, #12",
+            "0009: IN [r+5]",
+            "0011: HLT",
+            "0012: DB 42",
+            "0013: DB 30101",
+            "0014: DB 1",
+            "0015: DB 2",
+        ],
+    );
+}
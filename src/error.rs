@@ -0,0 +1,173 @@
+use std::fmt;
+use std::io;
+
+use crate::{Cell, State};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedSign,
+    InvalidCharacter(u8),
+    // Number doesn't fit in a cell
+    Overflow,
+    // `-` without digits
+    LoneSign,
+    // Nothing between two commas, or before the first one
+    EmptyField,
+    // Two numbers without a comma between them
+    MissingComma,
+}
+
+// Errors from the Intcode VM. `counter` is the address of the instruction
+// being executed, and `opcode` the raw value found there.
+#[derive(Debug)]
+pub enum IntcodeError<C = i64> {
+    InvalidOpcode { counter: usize, opcode: C },
+    InvalidParameterMode { counter: usize, opcode: C, mode: i64 },
+    NegativeAddress { counter: usize, opcode: C, address: C },
+    WriteToImmediate { counter: usize, opcode: C },
+    MemoryLimit { counter: usize, opcode: C, address: C, executed: u64 },
+    InstructionLimit { counter: usize, executed: u64 },
+    OutputLimit { counter: usize, opcode: C, executed: u64 },
+    InvalidJumpTarget { counter: usize, opcode: C, target: C },
+    InputExhausted { counter: usize, opcode: C },
+    OutputRejected { counter: usize, opcode: C, value: C },
+    Overflow { counter: usize, opcode: C, operands: (C, C) },
+    NotRunnable { counter: usize, state: State },
+    EndOfMemory { counter: usize },
+    // Line and column start at 1
+    ParseError { line: usize, column: usize, kind: ParseErrorKind },
+    // `message` names the field that couldn't be read
+    InvalidSnapshot { line: usize, message: &'static str },
+    InvalidBinary { position: usize, message: &'static str },
+    Io(io::Error),
+}
+
+impl<C> IntcodeError<C> {
+    // Address of the instruction the error happened on, if any
+    pub fn counter(&self) -> Option<usize> {
+        match self {
+            IntcodeError::InvalidOpcode { counter, .. }
+            | IntcodeError::InvalidParameterMode { counter, .. }
+            | IntcodeError::NegativeAddress { counter, .. }
+            | IntcodeError::WriteToImmediate { counter, .. }
+            | IntcodeError::MemoryLimit { counter, .. }
+            | IntcodeError::InstructionLimit { counter, .. }
+            | IntcodeError::OutputLimit { counter, .. }
+            | IntcodeError::InvalidJumpTarget { counter, .. }
+            | IntcodeError::InputExhausted { counter, .. }
+            | IntcodeError::OutputRejected { counter, .. }
+            | IntcodeError::Overflow { counter, .. }
+            | IntcodeError::NotRunnable { counter, .. }
+            | IntcodeError::EndOfMemory { counter } => Some(*counter),
+            IntcodeError::ParseError { .. }
+            | IntcodeError::InvalidSnapshot { .. }
+            | IntcodeError::InvalidBinary { .. }
+            | IntcodeError::Io(_) => None,
+        }
+    }
+}
+
+impl<C: Cell> fmt::Display for IntcodeError<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::InvalidOpcode { counter, opcode } => {
+                write!(f, "Invalid opcode {} at position {}", opcode, counter)
+            }
+            IntcodeError::InvalidParameterMode { counter, opcode, mode } => write!(
+                f,
+                "Invalid parameter mode {} in instruction {} at position {}",
+                mode, opcode, counter,
+            ),
+            IntcodeError::NegativeAddress { counter, opcode, address } => write!(
+                f,
+                "Access to negative address {} by instruction {} at position {}",
+                address, opcode, counter,
+            ),
+            IntcodeError::WriteToImmediate { counter, opcode } => write!(
+                f,
+                "Can't write on immediate value in instruction {} at position {}",
+                opcode, counter,
+            ),
+            IntcodeError::MemoryLimit { counter, opcode, address, executed } => write!(
+                f,
+                "Can't write to address {} for instruction {} at position {} \
+                 (after {} instructions)",
+                address, opcode, counter, executed,
+            ),
+            IntcodeError::InstructionLimit { counter, executed } => write!(
+                f,
+                "Instruction limit reached at position {} (after {} instructions)",
+                counter, executed,
+            ),
+            IntcodeError::OutputLimit { counter, opcode, executed } => write!(
+                f,
+                "Output limit reached for instruction {} at position {} \
+                 (after {} instructions)",
+                opcode, counter, executed,
+            ),
+            IntcodeError::InvalidJumpTarget { counter, opcode, target } => write!(
+                f,
+                "Attempt to jump to {} by instruction {} at position {}",
+                target, opcode, counter,
+            ),
+            IntcodeError::InputExhausted { counter, opcode } => write!(
+                f,
+                "No input available for instruction {} at position {}",
+                opcode, counter,
+            ),
+            IntcodeError::OutputRejected { counter, opcode, value } => write!(
+                f,
+                "Output {} rejected for instruction {} at position {}",
+                value, opcode, counter,
+            ),
+            IntcodeError::Overflow { counter, opcode, operands: (a, b) } => write!(
+                f,
+                "Overflow on operands {} and {} of instruction {} at position {}",
+                a, b, opcode, counter,
+            ),
+            IntcodeError::NotRunnable { counter, state } => write!(
+                f,
+                "Can't run machine in state {:?} at position {}",
+                state, counter,
+            ),
+            IntcodeError::EndOfMemory { counter } => {
+                write!(f, "Ran off the end of memory at position {}", counter)
+            }
+            IntcodeError::ParseError { line, column, kind } => {
+                write!(f, "Parse error at line {}, column {}: ", line, column)?;
+                match kind {
+                    ParseErrorKind::UnexpectedSign => write!(f, "unexpected - sign"),
+                    ParseErrorKind::InvalidCharacter(byte) => {
+                        write!(f, "invalid character 0x{:x}", byte)
+                    }
+                    ParseErrorKind::Overflow => write!(f, "number too large"),
+                    ParseErrorKind::LoneSign => write!(f, "- sign without a number"),
+                    ParseErrorKind::EmptyField => write!(f, "missing number"),
+                    ParseErrorKind::MissingComma => write!(f, "missing comma"),
+                }
+            }
+            IntcodeError::InvalidSnapshot { line, message } => {
+                write!(f, "Invalid snapshot at line {}: {}", line, message)
+            }
+            IntcodeError::InvalidBinary { position, message } => {
+                write!(f, "Invalid binary program at {}: {}", position, message)
+            }
+            IntcodeError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl<C: Cell> std::error::Error for IntcodeError<C> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IntcodeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<C> From<io::Error> for IntcodeError<C> {
+    fn from(e: io::Error) -> IntcodeError<C> {
+        IntcodeError::Io(e)
+    }
+}
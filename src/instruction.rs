@@ -0,0 +1,287 @@
+use std::fmt;
+
+use crate::{Cell, IntcodeError, Memory};
+
+// Operation of an instruction, the last two digits of its first cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Opcode {
+    Add = 1,
+    Multiply = 2,
+    Input = 3,
+    Output = 4,
+    JumpIfTrue = 5,
+    JumpIfFalse = 6,
+    LessThan = 7,
+    Equals = 8,
+    AdjustRelativeBase = 9,
+    Halt = 99,
+}
+
+const OPCODES: [Opcode; 10] = [
+    Opcode::Add,
+    Opcode::Multiply,
+    Opcode::Input,
+    Opcode::Output,
+    Opcode::JumpIfTrue,
+    Opcode::JumpIfFalse,
+    Opcode::LessThan,
+    Opcode::Equals,
+    Opcode::AdjustRelativeBase,
+    Opcode::Halt,
+];
+
+impl Opcode {
+    // The opcode for a code without the parameter modes
+    pub fn from_code(code: i64) -> Option<Opcode> {
+        match code {
+            1..=9 => Some(OPCODES[code as usize - 1]),
+            99 => Some(Opcode::Halt),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> i64 {
+        self as i64
+    }
+
+    // Short name, as used by the assembler and disassembler
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Multiply => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpIfTrue => "JT",
+            Opcode::JumpIfFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equals => "EQ",
+            Opcode::AdjustRelativeBase => "ARB",
+            Opcode::Halt => "HLT",
+        }
+    }
+
+    // Case insensitive
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        OPCODES
+            .iter()
+            .cloned()
+            .find(|op| op.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
+
+    pub fn parameter_count(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::AdjustRelativeBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parameter<C = i64> {
+    Position(C),
+    Immediate(C),
+    Relative(C),
+}
+
+impl<C: Cell> Parameter<C> {
+    // The mode digit: 0 for position, 1 for immediate, 2 for relative
+    pub fn mode(&self) -> i64 {
+        match self {
+            Parameter::Position(_) => 0,
+            Parameter::Immediate(_) => 1,
+            Parameter::Relative(_) => 2,
+        }
+    }
+
+    // The raw value, as stored after the opcode
+    pub fn value(&self) -> &C {
+        match self {
+            Parameter::Position(v) | Parameter::Immediate(v) | Parameter::Relative(v) => v,
+        }
+    }
+}
+
+impl<C: Cell> fmt::Display for Parameter<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::Position(addr) => write!(f, "[{}]", addr),
+            Parameter::Immediate(value) => write!(f, "#{}", value),
+            Parameter::Relative(offset) => {
+                if offset.is_negative() {
+                    write!(f, "[r{}]", offset)
+                } else {
+                    write!(f, "[r+{}]", offset)
+                }
+            }
+        }
+    }
+}
+
+// A decoded instruction: an opcode and as many parameters as it takes. The
+// parameters are kept inline, as decoding happens on every step.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Instruction<C = i64> {
+    opcode: Opcode,
+    // Unused ones are `Immediate(0)`
+    parameters: [Parameter<C>; 3],
+}
+
+impl<C: Cell> Instruction<C> {
+    // Checks that there are as many parameters as the opcode takes
+    pub fn new(opcode: Opcode, parameters: &[Parameter<C>]) -> Option<Instruction<C>> {
+        if parameters.len() != opcode.parameter_count() {
+            return None;
+        }
+        let mut inline = Instruction::unused();
+        inline[..parameters.len()].clone_from_slice(parameters);
+        Some(Instruction { opcode, parameters: inline })
+    }
+
+    fn unused() -> [Parameter<C>; 3] {
+        [
+            Parameter::Immediate(C::zero()),
+            Parameter::Immediate(C::zero()),
+            Parameter::Immediate(C::zero()),
+        ]
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn parameters(&self) -> &[Parameter<C>] {
+        &self.parameters[..self.opcode.parameter_count()]
+    }
+
+    // Number of cells taken in memory
+    pub fn length(&self) -> usize {
+        1 + self.opcode.parameter_count()
+    }
+
+    // All three slots, for the machine to take them apart
+    pub(crate) fn into_parts(self) -> (Opcode, [Parameter<C>; 3]) {
+        (self.opcode, self.parameters)
+    }
+}
+
+impl<C: Cell> fmt::Debug for Instruction<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Instruction")
+            .field("opcode", &self.opcode)
+            .field("parameters", &self.parameters())
+            .finish()
+    }
+}
+
+impl<C: Cell> fmt::Display for Instruction<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode)?;
+        for (i, param) in self.parameters().iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, param)?;
+        }
+        Ok(())
+    }
+}
+
+// Decode the instruction at this address. Mode digits past the last
+// parameter are ignored, like the machine does.
+pub fn decode<C: Cell, M: Memory<C> + ?Sized>(
+    memory: &M,
+    address: usize,
+) -> Result<Instruction<C>, IntcodeError<C>> {
+    decode_with_code(memory, address).map(|(_, instruction)| instruction)
+}
+
+// Also returns the first cell as read, modes included, for error reporting
+#[inline(always)]
+pub(crate) fn decode_with_code<C: Cell, M: Memory<C> + ?Sized>(
+    memory: &M,
+    address: usize,
+) -> Result<(C, Instruction<C>), IntcodeError<C>> {
+    let code = memory.read(address);
+    let invalid = || IntcodeError::InvalidOpcode { counter: address, opcode: code.clone() };
+    let (opcode, mut modes) = match code.to_i64() {
+        Some(c) if c > 0 => (Opcode::from_code(c % 100).ok_or_else(invalid)?, c / 100),
+        _ => return Err(invalid()),
+    };
+    let mut parameters = Instruction::unused();
+    for (i, param) in parameters.iter_mut().take(opcode.parameter_count()).enumerate() {
+        let value = memory.read(address + 1 + i);
+        *param = match modes % 10 {
+            0 => Parameter::Position(value),
+            1 => Parameter::Immediate(value),
+            2 => Parameter::Relative(value),
+            mode => {
+                return Err(IntcodeError::InvalidParameterMode {
+                    counter: address,
+                    opcode: code,
+                    mode,
+                });
+            }
+        };
+        modes /= 10;
+    }
+    Ok((code, Instruction { opcode, parameters }))
+}
+
+// The cells for an instruction: the opcode with its modes, then the parameters
+pub fn encode<C: Cell>(instruction: &Instruction<C>) -> Vec<C> {
+    let mut code = instruction.opcode.code();
+    let mut factor = 100;
+    for param in instruction.parameters() {
+        code += param.mode() * factor;
+        factor *= 10;
+    }
+    let mut cells = Vec::with_capacity(instruction.length());
+    cells.push(C::from_i64(code));
+    cells.extend(instruction.parameters().iter().map(|p| p.value().clone()));
+    cells
+}
+
+#[test]
+fn test_decode() {
+    use crate::DenseMemory;
+
+    let memory: DenseMemory =
+        vec![1002, 421, 422, 423, 301, 0, -5, 22107, 1, -2, 3, 10099].into();
+    let instr = decode(&memory, 0).unwrap();
+    let params = [Parameter::Position(421), Parameter::Immediate(422), Parameter::Position(423)];
+    assert_eq!(Some(&instr), Instruction::new(Opcode::Multiply, &params).as_ref());
+    assert_eq!(instr.parameters(), &params);
+    assert_eq!(instr.to_string(), "MUL [421], #422, [423]");
+    assert_eq!(encode(&instr), vec![1002, 421, 422, 423]);
+
+    match decode(&memory, 4) {
+        Err(IntcodeError::InvalidParameterMode { counter: 4, opcode: 301, mode: 3 }) => {}
+        r => panic!("{:?}", r),
+    }
+    match decode(&memory, 6) {
+        Err(IntcodeError::InvalidOpcode { counter: 6, opcode: -5 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    // Encoding round-trips, except for modes past the last parameter
+    let instr = decode(&memory, 7).unwrap();
+    assert_eq!(instr.to_string(), "LT #1, [r-2], [r+3]");
+    assert_eq!(encode(&instr), vec![22107, 1, -2, 3]);
+    let instr = decode(&memory, 11).unwrap();
+    assert_eq!((instr.opcode(), instr.length()), (Opcode::Halt, 1));
+    assert_eq!(encode(&instr), vec![99]);
+
+    // The parameters have to match the opcode
+    assert_eq!(Instruction::<i64>::new(Opcode::Add, &[]), None);
+    assert!(Instruction::new(Opcode::Output, &[Parameter::Relative(-1i64)]).is_some());
+
+    assert_eq!(Opcode::from_mnemonic("arb"), Some(Opcode::AdjustRelativeBase));
+    assert_eq!(Opcode::from_code(8).map(Opcode::code), Some(8));
+    assert_eq!(Opcode::from_code(10), None);
+}
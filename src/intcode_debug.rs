@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use adventofcode2019::{Breakpoint, IntcodeError, Memory, Program, Res, State, StopReason};
+use adventofcode2019::disasm::disassemble_one;
+
+const HELP: &str = "\
+Commands:
+  s, step [N]         execute N instructions (default 1)
+  c, continue         run until a breakpoint, halt, error or missing input
+  b, break [ADDR]     set a breakpoint, or list them
+  d, delete ADDR      clear a breakpoint
+  i, info             show the counter, relative base and next instruction
+  x, dump ADDR [N]    show N memory cells (default 8)
+  w, write ADDR V...  write values to memory, starting at ADDR
+  in V...             queue input values
+  reset               restart the program from the beginning
+  h, help             show this message
+  q, quit             exit";
+
+struct Debugger {
+    program: Program,
+    // Ids of the program's breakpoints, by address
+    breakpoints: BTreeMap<usize, usize>,
+}
+
+impl Debugger {
+    // Execute one instruction, with input from the program's queue. Returns
+    // false if the program can't go on.
+    fn step(&mut self) -> bool {
+        let result = self.program.step_queued();
+        self.report(result)
+    }
+
+    // Show why the program stopped, if it did. Returns false if it can't go
+    // on without the user.
+    fn report(&self, result: Result<Option<StopReason>, IntcodeError>) -> bool {
+        match result {
+            Ok(None) => true,
+            Ok(Some(StopReason::Output(v))) => {
+                println!("Output: {}", v);
+                true
+            }
+            Ok(Some(StopReason::NeedsInput)) => {
+                println!("Waiting for input (use `in`)");
+                false
+            }
+            Ok(Some(StopReason::Halted)) => {
+                println!("Halted ({:?})", self.program.halt_reason().unwrap());
+                false
+            }
+            Ok(Some(StopReason::Breakpoint(_))) => {
+                println!("Breakpoint at {}", self.program.counter);
+                false
+            }
+            Ok(Some(stop)) => {
+                println!("Stopped: {:?}", stop);
+                false
+            }
+            Err(e) => {
+                println!("Error: {}", e);
+                false
+            }
+        }
+    }
+
+    // Execute this many instructions, or until something stops the program
+    fn run(&mut self, count: Option<u64>) {
+        match count {
+            Some(count) => {
+                for _ in 0..count {
+                    if !self.step() {
+                        break;
+                    }
+                }
+            }
+            None => loop {
+                let result = self.program.resume().map(Some);
+                if !self.report(result) {
+                    break;
+                }
+            },
+        }
+        self.show_instruction();
+    }
+
+    fn show_instruction(&self) {
+        if self.program.state() == State::Halted || self.program.state() == State::Faulted {
+            return;
+        }
+        println!("{}", disassemble_one(&self.program.memory, self.program.counter));
+    }
+
+    fn info(&self) {
+        println!(
+            "counter: {}  relative_base: {}  state: {:?}  instructions: {}",
+            self.program.counter,
+            self.program.relative_base,
+            self.program.state(),
+            self.program.instructions(),
+        );
+        println!("queued input: {:?}", self.program.queued_input());
+        self.show_instruction();
+    }
+
+    fn dump(&self, start: usize, count: usize) {
+        for (i, addr) in (start..start.saturating_add(count)).enumerate() {
+            if i % 8 == 0 {
+                if i > 0 {
+                    println!();
+                }
+                print!("{:04}:", addr);
+            }
+            print!(" {}", self.program.memory.read(addr));
+        }
+        println!();
+    }
+
+    // Handle one command line. Returns false to quit.
+    fn command(&mut self, line: &str) -> Res<bool> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(c) => c,
+            None => return Ok(true),
+        };
+        let args = words.collect::<Vec<_>>();
+        let number = |i: usize| -> Res<Option<i64>> {
+            match args.get(i) {
+                Some(arg) => Ok(Some(arg.parse()?)),
+                None => Ok(None),
+            }
+        };
+        let address = |i: usize| -> Res<usize> {
+            match args.get(i) {
+                Some(arg) => Ok(arg.parse()?),
+                None => Err("missing address".into()),
+            }
+        };
+
+        match command {
+            "s" | "step" => self.run(Some(number(0)?.unwrap_or(1).max(1) as u64)),
+            "c" | "continue" => self.run(None),
+            "b" | "break" if args.is_empty() => {
+                for addr in self.breakpoints.keys() {
+                    println!("{}", disassemble_one(&self.program.memory, *addr));
+                }
+            }
+            "b" | "break" => {
+                let addr = address(0)?;
+                if !self.breakpoints.contains_key(&addr) {
+                    let id = self.program.add_breakpoint(Breakpoint::Address(addr));
+                    self.breakpoints.insert(addr, id);
+                }
+            }
+            "d" | "delete" => match self.breakpoints.remove(&address(0)?) {
+                Some(id) => {
+                    self.program.remove_breakpoint(id);
+                }
+                None => println!("No breakpoint there"),
+            },
+            "i" | "info" => self.info(),
+            "x" | "dump" => {
+                let count = number(1)?.unwrap_or(8).max(0) as usize;
+                self.dump(address(0)?, count);
+            }
+            "w" | "write" => {
+                // Same limit as the machine, checked before writing anything
+                let start = address(0)?;
+                let end = start.saturating_add(args.len().saturating_sub(1));
+                if end > self.program.limits.max_memory {
+                    return Err(format!(
+                        "address past the memory limit of {}",
+                        self.program.limits.max_memory
+                    )
+                    .into());
+                }
+                for i in 1..args.len() {
+                    self.program.memory.write(start + i - 1, number(i)?.unwrap());
+                }
+            }
+            "in" => {
+                for i in 0..args.len() {
+                    self.program.push_input(number(i)?.unwrap());
+                }
+            }
+            "reset" => {
+                self.program.reset();
+                self.show_instruction();
+            }
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(false),
+            _ => println!("Unknown command {:?}, try `help`", command),
+        }
+        Ok(true)
+    }
+}
+
+fn main() -> Res<()> {
+    let path = env::args().nth(1).ok_or("Usage: intcode-debug PROGRAM")?;
+    let program = Program::from_reader(BufReader::new(File::open(path)?))?;
+    let mut debugger = Debugger {
+        program,
+        breakpoints: BTreeMap::new(),
+    };
+    debugger.show_instruction();
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        match debugger.command(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+
+    Ok(())
+}
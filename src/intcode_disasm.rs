@@ -0,0 +1,21 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use adventofcode2019::{Res, Program};
+use adventofcode2019::disasm::disassemble;
+
+fn main() -> Res<()> {
+    // Read the program from the file given, or standard input
+    let program = match env::args().nth(1) {
+        Some(path) => Program::from_reader(BufReader::new(File::open(path)?))?,
+        None => Program::from_reader(io::stdin().lock())?,
+    };
+
+    // Print the listing
+    for line in disassemble(&program.memory) {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
@@ -1,264 +1,1311 @@
-use std::io::Read;
+use std::collections::VecDeque;
+use std::collections::vec_deque::Drain;
+use std::io::{self, Read, Write};
+
+pub mod asm;
+mod bigint;
+mod binary;
+mod breakpoint;
+mod cell;
+mod coverage;
+pub mod disasm;
+mod error;
+mod instruction;
+mod memory;
+mod profile;
+mod snapshot;
+mod trace;
+
+pub use bigint::BigInt;
+pub use binary::{read_any, read_binary, write_binary, BINARY_MAGIC, BINARY_VERSION};
+pub use breakpoint::{Access, Breakpoint, Watchpoint};
+pub use cell::Cell;
+pub use coverage::{coverage_from_env, Coverage, CoverageSummary, COVERAGE_ENV};
+pub use error::{IntcodeError, ParseErrorKind};
+pub use instruction::{decode, encode, Instruction, Opcode, Parameter};
+pub use memory::{DenseMemory, Image, Memory, PagedMemory, SparseMemory, PAGE_SIZE};
+pub use profile::{profiler_from_env, Profiler, PROFILE_ENV};
+pub use snapshot::SNAPSHOT_VERSION;
+pub use trace::{
+    json_tracer_from_env, CollectingTracer, JsonTracer, PrintTracer, SharedTracer, TraceEvent,
+    Tracer, TRACE_ENV,
+};
 
 pub type Res<O> = Result<O, Box<dyn std::error::Error>>;
 
-const MAX_MEMORY: usize = 1 << 32;
+// A number being read
+struct Field<C> {
+    negative: bool,
+    digits: usize,
+    number: C,
+    line: usize,
+    column: usize,
+}
+
+// Read comma-separated numbers. Whitespace (including newlines) can go around
+// the numbers, `#` starts a comment until the end of the line, and there can
+// be a comma after the last number.
+pub fn read_program<C: Cell, R: Read>(mut file: R) -> Result<Vec<C>, IntcodeError<C>> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
 
-fn read_program<R: Read>(mut file: R) -> Res<Vec<i64>> {
     let mut memory = Vec::new();
+    let ten = C::from_i64(10);
+    let (mut line, mut column) = (1, 0);
+    let error = |line, column, kind| IntcodeError::ParseError { line, column, kind };
+    let mut field: Option<Field<C>> = None;
+    // A number was read, and the comma after it wasn't
+    let mut need_comma = false;
+    let mut in_comment = false;
 
-    let mut position = 0;
-    let mut negative = false;
-    let mut number: i64 = 0;
-    loop {
-        let byte = {
-            let mut buf = [0u8];
-            match file.read(&mut buf)? {
-                0 => b'\n',
-                1 => buf[0],
-                _ => panic!("Invalid return from read()"),
+    // Push the number being read, if any
+    let mut finish = |field: &mut Option<Field<C>>, need_comma: &mut bool| match field.take() {
+        Some(f) if f.digits == 0 => Err(error(f.line, f.column, ParseErrorKind::LoneSign)),
+        Some(f) => {
+            memory.push(f.number);
+            *need_comma = true;
+            Ok(())
+        }
+        None => Ok(()),
+    };
+
+    for &byte in &bytes {
+        column += 1;
+        if in_comment {
+            in_comment = byte != b'\n';
+        } else if byte == b'#' {
+            finish(&mut field, &mut need_comma)?;
+            in_comment = true;
+        } else if byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n' {
+            finish(&mut field, &mut need_comma)?;
+        } else if byte == b',' {
+            finish(&mut field, &mut need_comma)?;
+            if !need_comma {
+                return Err(error(line, column, ParseErrorKind::EmptyField));
             }
-        };
-        if byte == b'-' {
-            if number != 0 || negative {
-                return Err(format!("Unexpected - sign at {}", position).into());
+            need_comma = false;
+        } else if byte == b'-' || byte.is_ascii_digit() {
+            if field.is_none() {
+                if need_comma {
+                    return Err(error(line, column, ParseErrorKind::MissingComma));
+                }
+                field = Some(Field {
+                    negative: false,
+                    digits: 0,
+                    number: C::zero(),
+                    line,
+                    column,
+                });
             }
-            negative = true;
-        } else if b'0' <= byte && byte <= b'9' {
-            number = number * 10 + (byte - b'0') as i64;
-        } else if byte == b',' || byte == b'\n' {
-            memory.push(if negative { -number } else { number });
-            number = 0;
-            negative = false;
-            if byte == b'\n' {
-                break;
+            let f = field.as_mut().unwrap();
+            if byte == b'-' {
+                if f.negative || f.digits > 0 {
+                    return Err(error(line, column, ParseErrorKind::UnexpectedSign));
+                }
+                f.negative = true;
+            } else {
+                // Negative numbers are built negative, so the minimum fits
+                let digit = (byte - b'0') as i64;
+                let digit = C::from_i64(if f.negative { -digit } else { digit });
+                f.number = match f.number.checked_mul(&ten).and_then(|n| n.checked_add(&digit)) {
+                    Some(n) => n,
+                    None => return Err(error(f.line, f.column, ParseErrorKind::Overflow)),
+                };
+                f.digits += 1;
             }
         } else {
-            return Err(
-                format!("Invalid character at {}: 0x{:x}", position, byte).into(),
-            );
+            return Err(error(line, column, ParseErrorKind::InvalidCharacter(byte)));
+        }
+        if byte == b'\n' {
+            line += 1;
+            column = 0;
         }
-        position += 1;
     }
+    finish(&mut field, &mut need_comma)?;
 
     Ok(memory)
 }
 
+#[test]
+fn test_read_program() {
+    let read = |text: &str| read_program::<i64, _>(text.as_bytes());
+    assert_eq!(read("1,-2,3\n").unwrap(), vec![1, -2, 3]);
+    assert_eq!(read("").unwrap(), vec![]);
+    assert_eq!(
+        read("# Header\r\n 1, 2,\t3, # three\r\n\n-4 ,\n5,\n").unwrap(),
+        vec![1, 2, 3, -4, 5],
+    );
+    assert_eq!(
+        read("9223372036854775807,-9223372036854775808").unwrap(),
+        vec![i64::MAX, i64::MIN],
+    );
+
+    let error = |text: &str| match read(text) {
+        Err(IntcodeError::ParseError { line, column, kind }) => (line, column, kind),
+        r => panic!("Unexpected result: {:?}", r),
+    };
+    assert_eq!(error("1,\n 9223372036854775808"), (2, 2, ParseErrorKind::Overflow));
+    assert_eq!(error("1,-,3"), (1, 3, ParseErrorKind::LoneSign));
+    assert_eq!(error("1,-"), (1, 3, ParseErrorKind::LoneSign));
+    assert_eq!(error("1,,3"), (1, 3, ParseErrorKind::EmptyField));
+    assert_eq!(error(",1"), (1, 1, ParseErrorKind::EmptyField));
+    assert_eq!(error("1\n2"), (2, 1, ParseErrorKind::MissingComma));
+    assert_eq!(error("1,2-3"), (1, 4, ParseErrorKind::UnexpectedSign));
+    assert_eq!(error("1,--3"), (1, 4, ParseErrorKind::UnexpectedSign));
+    assert_eq!(error("1;2"), (1, 2, ParseErrorKind::InvalidCharacter(b';')));
+
+    // Bigger cells take bigger numbers
+    let big = read_program::<i128, _>(&b"9223372036854775808"[..]).unwrap();
+    assert_eq!(big, vec![1 << 63]);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    // Leave out zeros at the end, e.g. from memory that grew. They read as
+    // zero anyway, so the program behaves the same.
+    pub trim_zeros: bool,
+    // Start a new line (after a comma) before going over this many bytes
+    pub line_width: Option<usize>,
+}
+
+// Write memory in the comma-separated format that `read_program()` reads
+// back. Without `trim_zeros`, `read_program()` returns exactly `memory`.
+pub fn write_program<C: Cell, W: Write>(
+    mut out: W,
+    memory: &[C],
+    options: &WriteOptions,
+) -> io::Result<()> {
+    let mut memory = memory;
+    if options.trim_zeros {
+        let len = memory.iter().rposition(|v| !v.is_zero()).map_or(0, |p| p + 1);
+        // Keep one zero on purpose: an empty line reads back as an empty
+        // program, while "0" still reads as one cell of zeroed memory
+        memory = &memory[..len.max(1).min(memory.len())];
+    }
+
+    let mut line_len = 0;
+    for (i, value) in memory.iter().enumerate() {
+        // The value, with the comma that follows it if it's not the last
+        let mut text = value.to_string();
+        if i + 1 < memory.len() {
+            text.push(',');
+        }
+        match options.line_width {
+            Some(width) if line_len > 0 && line_len + text.len() > width => {
+                writeln!(out)?;
+                line_len = 0;
+            }
+            _ => {}
+        }
+        write!(out, "{}", text)?;
+        line_len += text.len();
+    }
+    writeln!(out)
+}
+
+#[test]
+fn test_write_program() {
+    let write = |memory: &[i64], options: &WriteOptions| {
+        let mut out = Vec::new();
+        write_program(&mut out, memory, options).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    let memory = vec![1, 0, -30, 400, 0, 0];
+    assert_eq!(write(&memory, &Default::default()), "1,0,-30,400,0,0\n");
+    let trim = WriteOptions { trim_zeros: true, ..Default::default() };
+    assert_eq!(write(&memory, &trim), "1,0,-30,400\n");
+    assert_eq!(write(&[0, 0], &trim), "0\n");
+    assert_eq!(read_program::<i64, _>(&b"0\n"[..]).unwrap(), vec![0]);
+    let wrap = WriteOptions { line_width: Some(6), ..Default::default() };
+    assert_eq!(write(&memory, &wrap), "1,0,\n-30,\n400,0,\n0\n");
+    // The comma at the end of a line counts too
+    let wrap = WriteOptions { line_width: Some(5), ..Default::default() };
+    assert_eq!(write(&[400, 0, 5], &wrap), "400,\n0,5\n");
+    assert_eq!(write(&[12345, 1], &wrap), "12345,\n1\n");
+
+    // Round-trips, including patched and wrapped programs
+    for day in &["02", "05", "09"] {
+        let file = std::fs::File::open(format!("inputs/day{}.txt", day)).unwrap();
+        let mut program = Program::from_reader(io::BufReader::new(file)).unwrap();
+        program.memory.write(1, 12);
+        program.memory.write(2, 2);
+        let mut memory = program.memory.to_vec();
+        for options in &[WriteOptions::default(), wrap] {
+            let text = write(&memory, options);
+            assert_eq!(read_program::<i64, _>(text.as_bytes()).unwrap(), memory);
+        }
+        let text = write(&memory, &WriteOptions { trim_zeros: true, line_width: Some(80) });
+        assert!(text.lines().all(|line| line.len() <= 80));
+        while memory.last() == Some(&0) {
+            memory.pop();
+        }
+        assert_eq!(read_program::<i64, _>(text.as_bytes()).unwrap(), memory);
+    }
+}
+
+// Where the current instruction is, for error reporting
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Location<C> {
+    counter: usize,
+    opcode: C,
+}
+
+// Out of line, to keep the untraced path fast
+#[cold]
+#[inline(never)]
+fn emit<C: Cell>(tracers: &[SharedTracer<C>], event: TraceEvent<C>) {
+    for tracer in tracers {
+        tracer.lock().unwrap().event(&event);
+    }
+}
+
+// What a single instruction did, as far as the caller is concerned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Effect<C> {
+    Continue,
+    NeedsInput,
+    Output(C),
+    Halted(HaltReason),
+}
+
+// Why a machine stopped for good
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Parameter {
-    Position(i64),
-    Immediate(i64),
-    Relative(i64),
+pub enum HaltReason {
+    // Reached a halt instruction (99)
+    Explicit,
+    // Counter went past the end of memory
+    EndOfMemory,
+    // An instruction failed
+    Fault,
 }
 
-struct ParameterDecoder(i64);
+// Why `Program::resume()` gave control back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason<C = i64> {
+    NeedsInput,
+    Output(C),
+    Halted,
+    // Stopped before executing the instruction at the counter
+    Breakpoint(usize),
+    // Stopped after the instruction that accessed this address
+    Watchpoint { id: usize, address: usize, access: Access },
+}
+
+// Lifecycle of a machine. A halted or faulted machine has to be reset
+// before it can run again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Ready,
+    Running,
+    AwaitingInput,
+    Halted,
+    Faulted,
+}
+
+// Bounds on what a machine may do, so untrusted programs can be run safely
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecutionLimits {
+    pub max_instructions: Option<u64>,
+    // Addresses at or above this can't be written
+    pub max_memory: usize,
+    pub max_outputs: Option<u64>,
+}
 
-impl ParameterDecoder {
-    fn decode_parameter(&mut self, value: i64) -> Res<Parameter> {
-        let code = self.0 % 10;
-        self.0 /= 10;
-        match code {
-            0 => Ok(Parameter::Position(value)),
-            1 => Ok(Parameter::Immediate(value)),
-            2 => Ok(Parameter::Relative(value)),
-            _ => Err(format!("Invalid parameter mode {}", code).into()),
+impl Default for ExecutionLimits {
+    fn default() -> ExecutionLimits {
+        ExecutionLimits {
+            max_instructions: None,
+            max_memory: 1 << 32,
+            max_outputs: None,
         }
     }
 }
 
-fn decode_instruction(code: i64) -> Res<(i64, ParameterDecoder)> {
-    if code <= 0 {
-        Err(format!("Invalid opcode {}", code).into())
-    } else {
-        let instr = code % 100;
-        let param_codes = code / 100;
-        Ok((instr, ParameterDecoder(param_codes)))
+// What to do when arithmetic overflows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Fail with an error
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating,
+}
+
+impl OverflowPolicy {
+    fn add<C: Cell>(self, a: &C, b: &C) -> Option<C> {
+        match self {
+            OverflowPolicy::Checked => a.checked_add(b),
+            OverflowPolicy::Wrapping => Some(a.wrapping_add(b)),
+            OverflowPolicy::Saturating => Some(a.saturating_add(b)),
+        }
+    }
+
+    fn mul<C: Cell>(self, a: &C, b: &C) -> Option<C> {
+        match self {
+            OverflowPolicy::Checked => a.checked_mul(b),
+            OverflowPolicy::Wrapping => Some(a.wrapping_mul(b)),
+            OverflowPolicy::Saturating => Some(a.saturating_mul(b)),
+        }
     }
 }
 
-#[test]
-fn test_decode() {
-    let (instr, mut decoder) = decode_instruction(1002).unwrap();
-    assert_eq!(instr, 2);
-    assert_eq!(decoder.decode_parameter(421).unwrap(), Parameter::Position(421));
-    assert_eq!(decoder.decode_parameter(422).unwrap(), Parameter::Immediate(422));
-    assert_eq!(decoder.decode_parameter(423).unwrap(), Parameter::Position(423));
+// What to restore to undo one instruction
+#[derive(Clone)]
+struct Undo<C> {
+    counter: usize,
+    relative_base: C,
+    // Old values, in the order they were overwritten, and the length of
+    // memory before, in case a write grew it
+    writes: Vec<(usize, C)>,
+    len: usize,
+    input: Option<C>,
+    instructions: u64,
+    outputs: u64,
+    state: State,
+    halt_reason: Option<HaltReason>,
 }
 
+// `M` is where memory is kept. It is part of the type rather than a trait
+// object so that reading and writing memory doesn't go through a virtual call
+// on every instruction.
 #[derive(Clone)]
-pub struct Program {
-    pub memory: Vec<i64>,
+pub struct Program<C: Cell = i64, M: Memory<C> = DenseMemory<C>> {
+    pub memory: M,
     pub counter: usize,
-    pub relative_base: i64,
+    pub relative_base: C,
+    // Running off the end of memory is an error rather than a halt
+    pub strict: bool,
+    pub limits: ExecutionLimits,
+    pub overflow: OverflowPolicy,
+    // Number of instructions executed and values output so far
+    instructions: u64,
+    outputs: u64,
+    input: VecDeque<C>,
+    output: VecDeque<C>,
+    state: State,
+    halt_reason: Option<HaltReason>,
+    // Original memory, to reset to
+    image: Image<C>,
+    // With their ids, shared between both kinds
+    breakpoints: Vec<(usize, Breakpoint<C, M>)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_break_id: usize,
+    // First watchpoint hit since `resume()` last reported one
+    watch_hit: Option<(usize, usize, Access)>,
+    // Address of the breakpoint we just stopped on, not to stop there again
+    // before an instruction runs
+    skip_break: Option<usize>,
+    tracers: Vec<SharedTracer<C>>,
+    // Undo log, if recording
+    history: Option<Vec<Undo<C>>>,
+    // Entry for the instruction being executed
+    undo: Option<Undo<C>>,
 }
 
 impl Program {
     pub fn new(memory: Vec<i64>) -> Program {
+        Program::from_cells(memory)
+    }
+
+    // Text or binary, see `read_any()`
+    pub fn from_reader<R: Read>(file: R) -> Result<Program, IntcodeError> {
+        Ok(Program::new(read_any(file)?))
+    }
+}
+
+impl<C: Cell> Program<C> {
+    // Like `new()`, for any type of cell. Memory is dense; `with_memory()`
+    // takes another backend.
+    pub fn from_cells(memory: Vec<C>) -> Program<C> {
+        Program::with_memory(memory, DenseMemory::new())
+    }
+
+    // Like `from_reader()`, for any type of cell
+    pub fn read_cells<R: Read>(file: R) -> Result<Program<C>, IntcodeError<C>> {
+        let memory = read_any(file)?;
+        Ok(Program::from_cells(memory))
+    }
+}
+
+impl<C: Cell, M: Memory<C>> Program<C, M> {
+    // Use a specific backend for memory
+    pub fn with_memory(memory: Vec<C>, mut backend: M) -> Program<C, M> {
+        let image: Image<C> = memory.into();
+        backend.load(&image);
         Program {
-            memory,
+            image,
+            memory: backend,
             counter: 0,
-            relative_base: 0,
+            relative_base: C::zero(),
+            limits: Default::default(),
+            overflow: Default::default(),
+            instructions: 0,
+            outputs: 0,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            strict: false,
+            state: State::Ready,
+            halt_reason: None,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_break_id: 0,
+            watch_hit: None,
+            skip_break: None,
+            tracers: Vec::new(),
+            history: None,
+            undo: None,
+        }
+    }
+
+    // Write the current memory out, with `write_program()`
+    pub fn write_memory<W: Write>(&self, out: W, options: &WriteOptions) -> io::Result<()> {
+        write_program(out, &self.memory.to_vec(), options)
+    }
+
+    // Keep an undo log of every instruction, for `step_back()` and
+    // `rewind_to()`. Turning it off drops the log.
+    pub fn set_recording(&mut self, enabled: bool) {
+        if !enabled {
+            self.history = None;
+        } else if self.history.is_none() {
+            self.history = Some(Vec::new());
+        }
+    }
+
+    // Number of instructions in the undo log
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.len())
+    }
+
+    // Undo the last instruction executed while recording. Consumed input goes
+    // back to the front of the input queue; output that was already returned
+    // is not taken back. Returns false if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(|h| h.pop()) {
+            Some(undo) => undo,
+            None => return false,
+        };
+        for (addr, old) in undo.writes.into_iter().rev() {
+            self.memory.write(addr, old);
+        }
+        self.memory.truncate(undo.len);
+        if let Some(value) = undo.input {
+            self.input.push_front(value);
+        }
+        self.counter = undo.counter;
+        self.relative_base = undo.relative_base;
+        self.instructions = undo.instructions;
+        self.outputs = undo.outputs;
+        self.state = undo.state;
+        self.halt_reason = undo.halt_reason;
+        self.watch_hit = None;
+        self.skip_break = None;
+        true
+    }
+
+    // Step back until only `step` instructions are left in the undo log.
+    // Returns false if the log isn't that long.
+    pub fn rewind_to(&mut self, step: usize) -> bool {
+        if step > self.history_len() {
+            return false;
+        }
+        while self.history_len() > step {
+            self.step_back();
+        }
+        true
+    }
+
+    // Replace all the tracers with this one, or none
+    pub fn set_tracer(&mut self, tracer: Option<SharedTracer<C>>) {
+        self.tracers.clear();
+        self.tracers.extend(tracer);
+    }
+
+    // Add a tracer, alongside the ones already installed. They get the events
+    // in the order they were added.
+    pub fn add_tracer(&mut self, tracer: SharedTracer<C>) {
+        self.tracers.push(tracer);
+    }
+
+    #[inline]
+    fn trace<F: FnOnce() -> TraceEvent<C>>(&self, event: F) {
+        if !self.tracers.is_empty() {
+            emit(&self.tracers, event());
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint<C, M>) -> usize {
+        self.next_break_id += 1;
+        self.breakpoints.push((self.next_break_id, breakpoint));
+        self.next_break_id
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.next_break_id += 1;
+        self.watchpoints.push((self.next_break_id, watchpoint));
+        self.next_break_id
+    }
+
+    // Remove a breakpoint or watchpoint. Returns false if there was none with
+    // this id.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|(i, _)| *i != id);
+        self.watchpoints.retain(|(i, _)| *i != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    fn watch(&mut self, addr: usize, access: Access) {
+        if self.watch_hit.is_none() {
+            if let Some((id, _)) = self.watchpoints.iter().find(|(_, w)| w.matches(addr, access)) {
+                self.watch_hit = Some((*id, addr, access));
+            }
         }
     }
 
-    pub fn from_reader<R: Read>(file: R) -> Res<Program> {
-        let memory = read_program(file)?;
-        Ok(Program::new(memory))
+    #[inline(always)]
+    fn read(&mut self, location: &Location<C>, pos: Parameter<C>) -> Result<C, IntcodeError<C>> {
+        let addr = match &pos {
+            Parameter::Immediate(v) => {
+                self.trace(|| TraceEvent::Operand {
+                    parameter: pos.clone(),
+                    address: None,
+                    value: v.clone(),
+                });
+                return Ok(v.clone());
+            }
+            Parameter::Position(addr) => addr.clone(),
+            Parameter::Relative(rel_addr) => self.add(location, &self.relative_base, rel_addr)?,
+        };
+        let (address, value) = match addr.to_usize() {
+            Some(addr) => {
+                if !self.watchpoints.is_empty() {
+                    self.watch(addr, Access::Read);
+                }
+                (Some(addr), self.memory.read(addr))
+            }
+            None if addr.is_negative() => {
+                return Err(IntcodeError::NegativeAddress {
+                    counter: location.counter,
+                    opcode: location.opcode.clone(),
+                    address: addr,
+                });
+            }
+            // Too far to have been written to
+            None => (None, C::zero()),
+        };
+        self.trace(|| TraceEvent::Operand {
+            parameter: pos,
+            address,
+            value: value.clone(),
+        });
+        Ok(value)
     }
 
-    fn read(&self, pos: Parameter) -> Res<i64> {
-        match pos {
-            Parameter::Position(addr) => {
-                if addr < 0 {
-                    Err("Read negative offset".into())
-                } else if addr as usize >= self.memory.len() {
-                    Ok(0)
-                } else {
-                    Ok(self.memory[addr as usize])
+    #[inline(always)]
+    fn write(
+        &mut self,
+        location: &Location<C>,
+        pos: Parameter<C>,
+        value: C,
+    ) -> Result<(), IntcodeError<C>> {
+        let addr = match pos {
+            Parameter::Position(addr) => addr,
+            Parameter::Relative(rel_addr) => self.add(location, &self.relative_base, &rel_addr)?,
+            Parameter::Immediate(_) => {
+                return Err(IntcodeError::WriteToImmediate {
+                    counter: location.counter,
+                    opcode: location.opcode.clone(),
+                });
+            }
+        };
+        if addr.is_negative() {
+            return Err(IntcodeError::NegativeAddress {
+                counter: location.counter,
+                opcode: location.opcode.clone(),
+                address: addr,
+            });
+        }
+        match addr.to_usize() {
+            Some(addr) if addr < self.limits.max_memory => {
+                if !self.watchpoints.is_empty() {
+                    self.watch(addr, Access::Write);
+                }
+                if let Some(undo) = &mut self.undo {
+                    undo.writes.push((addr, self.memory.read(addr)));
                 }
+                if !self.tracers.is_empty() {
+                    let old = self.memory.read(addr);
+                    self.trace(|| TraceEvent::Write {
+                        address: addr,
+                        old,
+                        new: value.clone(),
+                    });
+                }
+                self.memory.write(addr, value);
+                Ok(())
             }
-            Parameter::Immediate(v) => Ok(v),
-            Parameter::Relative(rel_addr) => {
-                let addr = self.relative_base + rel_addr;
-                self.read(Parameter::Position(addr))
+            _ => Err(IntcodeError::MemoryLimit {
+                counter: location.counter,
+                opcode: location.opcode.clone(),
+                address: addr,
+                executed: self.instructions,
+            }),
+        }
+    }
+
+    fn add(&self, location: &Location<C>, a: &C, b: &C) -> Result<C, IntcodeError<C>> {
+        self.overflow.add(a, b).ok_or_else(|| IntcodeError::Overflow {
+            counter: location.counter,
+            opcode: location.opcode.clone(),
+            operands: (a.clone(), b.clone()),
+        })
+    }
+
+    fn mul(&self, location: &Location<C>, a: &C, b: &C) -> Result<C, IntcodeError<C>> {
+        self.overflow.mul(a, b).ok_or_else(|| IntcodeError::Overflow {
+            counter: location.counter,
+            opcode: location.opcode.clone(),
+            operands: (a.clone(), b.clone()),
+        })
+    }
+
+    fn jump(&mut self, location: &Location<C>, target: C) -> Result<(), IntcodeError<C>> {
+        match target.to_usize() {
+            Some(target) => {
+                self.counter = target;
+                Ok(())
             }
+            None => Err(IntcodeError::InvalidJumpTarget {
+                counter: location.counter,
+                opcode: location.opcode.clone(),
+                target,
+            }),
         }
     }
 
-    fn write(&mut self, pos: Parameter, value: i64) -> Res<()> {
-        match pos {
-            Parameter::Position(addr) => {
-                if addr < 0 {
-                    Err("Write negative offset".into())
-                } else {
-                    let addr = addr as usize;
-                    if addr >= self.memory.len() {
-                        if addr < MAX_MEMORY {
-                            self.memory.resize(addr + 1, 0);
-                        } else {
-                            return Err(
-                                format!("Can't grow memory to {}", addr + 1).into(),
-                            );
+    // Execute a single instruction. If it is an input instruction and no
+    // input is provided, the counter is left on it and `NeedsInput` is
+    // returned. Forced inline like `read()`, `write()` and `advance()`: left
+    // as calls, they cost about a third of the time of a simple instruction.
+    #[inline(always)]
+    fn execute(&mut self, input: &mut Option<C>) -> Result<Effect<C>, IntcodeError<C>> {
+        if self.counter >= self.memory.len() {
+            if self.strict {
+                Err(IntcodeError::EndOfMemory { counter: self.counter })
+            } else {
+                Ok(Effect::Halted(HaltReason::EndOfMemory))
+            }
+        } else {
+            let counter = self.counter;
+            let (code, instruction) = instruction::decode_with_code(&self.memory, counter)?;
+            let (opcode, [p1, p2, p3]) = instruction.into_parts();
+            if opcode == Opcode::Input && input.is_none() {
+                // Come back to this instruction once we have input
+                return Ok(Effect::NeedsInput);
+            }
+            let location = Location { counter, opcode: code };
+            self.trace(|| TraceEvent::Instruction {
+                counter,
+                opcode: location.opcode.clone(),
+                instr: opcode,
+            });
+            self.counter = counter + 1 + opcode.parameter_count();
+            match opcode {
+                Opcode::Halt => return Ok(Effect::Halted(HaltReason::Explicit)),
+                Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                    let op1 = self.read(&location, p1)?;
+                    let op2 = self.read(&location, p2)?;
+                    let result = match opcode {
+                        Opcode::Add => self.add(&location, &op1, &op2)?,
+                        Opcode::Multiply => self.mul(&location, &op1, &op2)?,
+                        Opcode::LessThan => C::from_i64(if op1 < op2 { 1 } else { 0 }),
+                        _ => C::from_i64(if op1 == op2 { 1 } else { 0 }),
+                    };
+                    self.write(&location, p3, result)?;
+                }
+                Opcode::Input => {
+                    // Checked above
+                    let value = input.take().unwrap();
+                    if let Some(undo) = &mut self.undo {
+                        undo.input = Some(value.clone());
+                    }
+                    self.trace(|| TraceEvent::Input(value.clone()));
+                    self.write(&location, p1, value)?;
+                }
+                Opcode::Output => {
+                    let op = self.read(&location, p1)?;
+                    if let Some(max) = self.limits.max_outputs {
+                        if self.outputs >= max {
+                            return Err(IntcodeError::OutputLimit {
+                                counter,
+                                opcode: location.opcode,
+                                executed: self.instructions,
+                            });
                         }
                     }
-                    self.memory[addr] = value;
-                    Ok(())
+                    self.outputs += 1;
+                    self.trace(|| TraceEvent::Output(op.clone()));
+                    return Ok(Effect::Output(op));
+                }
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let op1 = self.read(&location, p1)?;
+                    let op2 = self.read(&location, p2)?;
+                    if op1.is_zero() == (opcode == Opcode::JumpIfFalse) {
+                        self.jump(&location, op2)?;
+                    }
+                }
+                Opcode::AdjustRelativeBase => {
+                    let op = self.read(&location, p1)?;
+                    let base = self.add(&location, &self.relative_base, &op)?;
+                    self.trace(|| TraceEvent::RelativeBase {
+                        old: self.relative_base.clone(),
+                        new: base.clone(),
+                    });
+                    self.relative_base = base;
+                }
+            }
+            Ok(Effect::Continue)
+        }
+    }
+
+    // Execute a single instruction, keeping track of the state
+    #[inline(always)]
+    fn advance(&mut self, input: &mut Option<C>) -> Result<Effect<C>, IntcodeError<C>> {
+        if self.state == State::Halted || self.state == State::Faulted {
+            return Err(IntcodeError::NotRunnable {
+                counter: self.counter,
+                state: self.state,
+            });
+        }
+        if self.history.is_some() {
+            self.begin_undo();
+        }
+        let effect = match self.limits.max_instructions {
+            Some(max) if self.instructions >= max => {
+                Err(IntcodeError::InstructionLimit {
+                    counter: self.counter,
+                    executed: self.instructions,
+                })
+            }
+            _ => self.execute(input),
+        };
+        self.state = match effect {
+            Ok(Effect::Continue) | Ok(Effect::Output(_)) => {
+                self.instructions += 1;
+                self.skip_break = None;
+                State::Running
+            }
+            Ok(Effect::NeedsInput) => State::AwaitingInput,
+            Ok(Effect::Halted(reason)) => {
+                if reason == HaltReason::Explicit {
+                    self.instructions += 1;
                 }
+                self.halt_reason = Some(reason);
+                State::Halted
             }
-            Parameter::Immediate(_) => Err("Can't write on immediate value".into()),
-            Parameter::Relative(rel_addr) => {
-                let addr = self.relative_base + rel_addr;
-                self.write(Parameter::Position(addr), value)
+            Err(_) => {
+                self.halt_reason = Some(HaltReason::Fault);
+                State::Faulted
             }
+        };
+        if self.undo.is_some() {
+            // Waiting for input didn't change anything
+            self.end_undo(!matches!(effect, Ok(Effect::NeedsInput)));
         }
+        effect
     }
 
-    fn get_parameter(
-        &mut self,
-        decoder: &mut ParameterDecoder,
-    ) -> Res<Parameter> {
-        let param = decoder.decode_parameter(self.memory[self.counter])?;
-        self.counter += 1;
-        Ok(param)
+    // Out of line, to keep the path without recording fast
+    #[inline(never)]
+    fn begin_undo(&mut self) {
+        self.undo = Some(Undo {
+            counter: self.counter,
+            relative_base: self.relative_base.clone(),
+            writes: Vec::new(),
+            len: self.memory.len(),
+            input: None,
+            instructions: self.instructions,
+            outputs: self.outputs,
+            state: self.state,
+            halt_reason: self.halt_reason,
+        });
     }
 
-    fn read_parameter(
-        &mut self,
-        decoder: &mut ParameterDecoder,
-    ) -> Res<i64> {
-        let op = self.get_parameter(decoder)?;
-        self.read(op)
+    #[inline(never)]
+    fn end_undo(&mut self, keep: bool) {
+        let undo = self.undo.take();
+        if keep {
+            self.history.as_mut().unwrap().extend(undo);
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        self.halt_reason
+    }
+
+    // Number of instructions executed since the start
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Put the machine back in its initial state, with the memory it was
+    // created with
+    pub fn reset(&mut self) {
+        self.memory.load(&self.image);
+        self.counter = 0;
+        self.relative_base = C::zero();
+        self.instructions = 0;
+        self.outputs = 0;
+        self.input.clear();
+        self.output.clear();
+        self.state = State::Ready;
+        self.halt_reason = None;
+        self.watch_hit = None;
+        self.skip_break = None;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
     }
 
     pub fn step<I, O>(
         &mut self,
         mut input: I,
         mut output: O,
-    ) -> Res<bool>
+    ) -> Result<bool, IntcodeError<C>>
     where
-        I: FnMut() -> Res<i64>,
-        O: FnMut(i64) -> Res<()>,
+        I: FnMut() -> Res<C>,
+        O: FnMut(C) -> Res<()>,
     {
-        if self.counter >= self.memory.len() {
-            Ok(false)
-        } else {
-            let instr = self.read(Parameter::Position(self.counter as i64))?;
-            self.counter += 1;
-            let (instr, mut decoder) = decode_instruction(instr)?;
-            if instr == 99 {
-                // Halt
-                return Ok(false);
-            } else if instr == 1 {
-                let op1 = self.read_parameter(&mut decoder)?;
-                let op2 = self.read_parameter(&mut decoder)?;
-                let target = self.get_parameter(&mut decoder)?;
-                self.write(target, op1 + op2)?;
-            } else if instr == 2 {
-                let op1 = self.read_parameter(&mut decoder)?;
-                let op2 = self.read_parameter(&mut decoder)?;
-                let target = self.get_parameter(&mut decoder)?;
-                self.write(target, op1 * op2)?;
-            } else if instr == 3 {
-                let target = self.get_parameter(&mut decoder)?;
-                self.write(target, input()?)?;
-            } else if instr == 4 {
-                let op = self.read_parameter(&mut decoder)?;
-                output(op)?;
-            } else if instr == 5 {
-                let op1 = self.read_parameter(&mut decoder)?;
-                let op2 = self.read_parameter(&mut decoder)?;
-                if op1 != 0 {
-                    if op2 < 0 {
-                        return Err(format!("Attempt to jump to {} at position {}", op2, self.counter).into());
-                    }
-                    self.counter = op2 as usize;
-                }
-            } else if instr == 6 {
-                let op1 = self.read_parameter(&mut decoder)?;
-                let op2 = self.read_parameter(&mut decoder)?;
-                if op1 == 0 {
-                    if op2 < 0 {
-                        return Err(format!("Attempt to jump to {} at position {}", op2, self.counter).into());
-                    }
-                    self.counter = op2 as usize;
+        let counter = self.counter;
+        let mut value = None;
+        // Breakpoints and watchpoints are only for `resume()`
+        self.watch_hit = None;
+        let mut effect = self.advance(&mut value)?;
+        if effect == Effect::NeedsInput {
+            let opcode = self.memory.read(counter);
+            value = Some(input().map_err(|_| {
+                IntcodeError::InputExhausted { counter, opcode }
+            })?);
+            effect = self.advance(&mut value)?;
+        }
+        match effect {
+            Effect::Continue => Ok(true),
+            Effect::Output(v) => {
+                if output(v.clone()).is_err() {
+                    self.state = State::Faulted;
+                    self.halt_reason = Some(HaltReason::Fault);
+                    return Err(IntcodeError::OutputRejected {
+                        counter,
+                        opcode: self.memory.read(counter),
+                        value: v,
+                    });
                 }
-            } else if instr == 7 {
-                let op1 = self.read_parameter(&mut decoder)?;
-                let op2 = self.read_parameter(&mut decoder)?;
-                let target = self.get_parameter(&mut decoder)?;
-                self.write(target, if op1 < op2 { 1 } else { 0 })?;
-            } else if instr == 8 {
-                let op1 = self.read_parameter(&mut decoder)?;
-                let op2 = self.read_parameter(&mut decoder)?;
-                let target = self.get_parameter(&mut decoder)?;
-                self.write(target, if op1 == op2 { 1 } else { 0 })?;
-            } else if instr == 9 {
-                let op = self.read_parameter(&mut decoder)?;
-                self.relative_base += op;
-            } else {
-                return Err(format!("Unknown instruction {} at position {}", instr, self.counter).into());
+                Ok(true)
             }
-            Ok(true)
+            Effect::Halted(_) => Ok(false),
+            Effect::NeedsInput => unreachable!(),
         }
     }
 
-    pub fn run<I, O>(&mut self, mut input: I, mut output: O) -> Res<()>
+    pub fn run<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+    ) -> Result<HaltReason, IntcodeError<C>>
     where
-        I: FnMut() -> Res<i64>,
-        O: FnMut(i64) -> Res<()>,
+        I: FnMut() -> Res<C>,
+        O: FnMut(C) -> Res<()>,
     {
         loop {
             if !self.step(&mut input, &mut output)? {
-                return Ok(());
+                return Ok(self.halt_reason.unwrap());
+            }
+        }
+    }
+
+    // Run until the program needs input, outputs a value, halts, or hits a
+    // breakpoint or watchpoint. Input is taken from the queue.
+    pub fn resume(&mut self) -> Result<StopReason<C>, IntcodeError<C>> {
+        loop {
+            if let Some(stop) = self.step_queued()? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    // Execute one instruction the way `resume()` does, taking input from the
+    // queue. Returns `None` if there is no reason to stop yet.
+    #[inline]
+    pub fn step_queued(&mut self) -> Result<Option<StopReason<C>>, IntcodeError<C>> {
+        if let Some((id, address, access)) = self.watch_hit.take() {
+            return Ok(Some(StopReason::Watchpoint { id, address, access }));
+        }
+        let stopped_here = self.skip_break == Some(self.counter);
+        if !stopped_here && self.state != State::Halted && self.state != State::Faulted {
+            let hit = self.breakpoints.iter().find(|(_, b)| b.matches(self));
+            if let Some((id, _)) = hit {
+                self.skip_break = Some(self.counter);
+                return Ok(Some(StopReason::Breakpoint(*id)));
             }
         }
+
+        let mut input = self.input.pop_front();
+        let effect = self.advance(&mut input);
+        if let Some(v) = input {
+            // Wasn't used, put it back
+            self.input.push_front(v);
+        }
+        Ok(match effect? {
+            Effect::Continue => None,
+            // Will run the same instruction again
+            Effect::NeedsInput => Some(StopReason::NeedsInput),
+            Effect::Output(v) => Some(StopReason::Output(v)),
+            Effect::Halted(_) => Some(StopReason::Halted),
+        })
+    }
+
+    // Queue a value for the next input instruction, then resume
+    pub fn resume_with_input(&mut self, value: C) -> Result<StopReason<C>, IntcodeError<C>> {
+        self.push_input(value);
+        self.resume()
+    }
+
+    // Run until the input queue runs dry or the program halts, collecting
+    // output in the buffer
+    pub fn run_buffered(&mut self) -> Result<StopReason<C>, IntcodeError<C>> {
+        loop {
+            match self.resume()? {
+                StopReason::Output(v) => self.output.push_back(v),
+                stop => return Ok(stop),
+            }
+        }
+    }
+
+    pub fn push_input(&mut self, value: C) {
+        self.input.push_back(value);
+    }
+
+    pub fn extend_input<T: IntoIterator<Item = C>>(&mut self, values: T) {
+        self.input.extend(values);
+    }
+
+    // Input waiting for an input instruction, oldest first
+    pub fn queued_input(&self) -> &VecDeque<C> {
+        &self.input
+    }
+
+    // Take the oldest value from the output buffer
+    pub fn take_output(&mut self) -> Option<C> {
+        self.output.pop_front()
+    }
+
+    pub fn drain_output(&mut self) -> Drain<'_, C> {
+        self.output.drain(..)
+    }
+}
+
+#[test]
+fn test_resume() {
+    // Read two numbers, output their sum and their product
+    let mut program = Program::new(vec![
+        3, 17, 3, 18, 1, 17, 18, 19, 4, 19, 2, 17, 18, 19, 4, 19, 99, 0, 0, 0,
+    ]);
+    assert_eq!(program.resume().unwrap(), StopReason::NeedsInput);
+    assert_eq!(program.counter, 0);
+    assert_eq!(program.resume().unwrap(), StopReason::NeedsInput);
+    assert_eq!(program.resume_with_input(6).unwrap(), StopReason::NeedsInput);
+    assert_eq!(program.counter, 2);
+    assert_eq!(program.resume_with_input(7).unwrap(), StopReason::Output(13));
+    assert_eq!(program.resume().unwrap(), StopReason::Output(42));
+    assert_eq!(program.resume().unwrap(), StopReason::Halted);
+
+    // A program can be handed to another thread
+    let mut program = Program::new(vec![104, 7, 99]);
+    let stop = std::thread::spawn(move || program.resume().unwrap()).join().unwrap();
+    assert_eq!(stop, StopReason::Output(7));
+}
+
+#[test]
+fn test_step_back() {
+    // Read two numbers, output their sum and their product
+    let memory = vec![3, 17, 3, 18, 1, 17, 18, 19, 4, 19, 2, 17, 18, 19, 4, 19, 99, 0, 0, 0];
+    let mut program = Program::new(memory.clone());
+    program.set_recording(true);
+    program.extend_input(vec![6, 7]);
+    assert_eq!(program.resume().unwrap(), StopReason::Output(13));
+    assert_eq!(program.resume().unwrap(), StopReason::Output(42));
+    assert_eq!(program.resume().unwrap(), StopReason::Halted);
+    assert_eq!(program.history_len(), 7);
+
+    // Undo the halt and the multiplication
+    assert!(program.step_back());
+    assert_eq!((program.state(), program.counter), (State::Running, 16));
+    assert!(program.step_back());
+    assert!(program.step_back());
+    assert_eq!((program.counter, program.memory.read(19)), (10, 13));
+
+    // All the way back, with the inputs back in the queue
+    assert!(!program.rewind_to(8));
+    assert!(program.rewind_to(0));
+    assert!(!program.step_back());
+    assert_eq!(program.memory.to_vec(), memory);
+    assert_eq!((program.counter, program.instructions()), (0, 0));
+    assert_eq!(program.resume().unwrap(), StopReason::Output(13));
+
+    // Faults can be undone too
+    program.memory.write(10, 42);
+    assert!(program.resume().is_err());
+    assert_eq!(program.state(), State::Faulted);
+    assert!(program.step_back());
+    assert_eq!((program.state(), program.counter), (State::Running, 10));
+
+    // Memory that grew shrinks back
+    let memory = vec![1101, 1, 1, 10, 99];
+    let mut program = Program::new(memory.clone());
+    program.set_recording(true);
+    assert_eq!(program.resume().unwrap(), StopReason::Halted);
+    assert_eq!(program.memory.len(), 11);
+    assert!(program.rewind_to(0));
+    assert_eq!(program.memory.to_vec(), memory);
+}
+
+#[test]
+fn test_buffers() {
+    // Output the sum of two inputs, twice
+    let mut program = Program::new(vec![
+        3, 13, 3, 14, 1, 13, 14, 15, 4, 15, 1105, 1, 0, 0, 0, 0,
+    ]);
+    program.extend_input(vec![1, 2, 30]);
+    assert_eq!(program.run_buffered().unwrap(), StopReason::NeedsInput);
+    assert_eq!(program.counter, 2);
+
+    // Cloning carries the pending input along
+    let mut other = program.clone();
+    other.push_input(10);
+    assert_eq!(other.run_buffered().unwrap(), StopReason::NeedsInput);
+    assert_eq!(other.drain_output().collect::<Vec<_>>(), vec![3, 40]);
+
+    program.push_input(400);
+    assert_eq!(program.run_buffered().unwrap(), StopReason::NeedsInput);
+    assert_eq!(program.take_output(), Some(3));
+    assert_eq!(program.take_output(), Some(430));
+    assert_eq!(program.take_output(), None);
+
+    // One instruction at a time
+    let mut program = Program::new(vec![3, 5, 4, 5, 99, 0]);
+    program.push_input(7);
+    assert_eq!(program.queued_input(), &[7]);
+    assert_eq!(program.step_queued().unwrap(), None);
+    assert!(program.queued_input().is_empty());
+    assert_eq!(program.step_queued().unwrap(), Some(StopReason::Output(7)));
+    assert_eq!(program.step_queued().unwrap(), Some(StopReason::Halted));
+}
+
+#[test]
+fn test_state() {
+    let mut program = Program::new(vec![3, 7, 4, 7, 99]);
+    assert_eq!(program.state(), State::Ready);
+    assert_eq!(program.resume().unwrap(), StopReason::NeedsInput);
+    assert_eq!(program.state(), State::AwaitingInput);
+    assert_eq!(program.resume_with_input(5).unwrap(), StopReason::Output(5));
+    assert_eq!(program.state(), State::Running);
+    assert_eq!(program.resume().unwrap(), StopReason::Halted);
+    assert_eq!(program.state(), State::Halted);
+    assert_eq!(program.memory.to_vec(), vec![3, 7, 4, 7, 99, 0, 0, 5]);
+
+    // Can't run it again
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::NotRunnable { counter: 5, state: State::Halted }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    // Unless it's reset
+    program.reset();
+    assert_eq!(program.state(), State::Ready);
+    assert_eq!(program.memory.to_vec(), vec![3, 7, 4, 7, 99]);
+    assert_eq!(program.resume_with_input(6).unwrap(), StopReason::Output(6));
+
+    // Faults stick too
+    let mut program = Program::new(vec![104, 1, 99]);
+    assert!(program.run(no_input, no_output).is_err());
+    assert_eq!(program.state(), State::Faulted);
+    match program.step(no_input, no_output) {
+        Err(IntcodeError::NotRunnable { state: State::Faulted, .. }) => {}
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn test_halt_reason() {
+    let mut program = Program::new(vec![1101, 1, 2, 5, 99, 0]);
+    assert_eq!(program.halt_reason(), None);
+    assert_eq!(program.run(no_input, no_output).unwrap(), HaltReason::Explicit);
+
+    // Jumps past the end, falls off
+    let mut program = Program::new(vec![1105, 1, 7, 99]);
+    assert_eq!(program.run(no_input, no_output).unwrap(), HaltReason::EndOfMemory);
+    assert_eq!(program.halt_reason(), Some(HaltReason::EndOfMemory));
+
+    let mut program = Program::new(vec![1105, 1, 7, 99]);
+    program.strict = true;
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::EndOfMemory { counter: 7 }) => {}
+        r => panic!("{:?}", r),
+    }
+    assert_eq!(program.halt_reason(), Some(HaltReason::Fault));
+}
+
+#[test]
+fn test_limits() {
+    // Loops forever
+    let mut program = Program::new(vec![1101, 1, 5, 5, 1105, 1, 0]);
+    program.limits.max_instructions = Some(10);
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::InstructionLimit { counter: 0, executed: 10 }) => {}
+        r => panic!("{:?}", r),
+    }
+    assert_eq!(program.instructions(), 10);
+
+    // Writes at growing addresses
+    let mut program = Program::new(vec![109, 100, 21101, 1, 1, 0, 109, 100, 1105, 1, 2]);
+    program.limits.max_memory = 1000;
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::MemoryLimit { counter: 2, address: 1000, executed: 28, .. }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    // Outputs forever
+    let mut program = Program::new(vec![104, 1, 1105, 1, 0]);
+    program.limits.max_outputs = Some(3);
+    match program.run(no_input, |_| Ok(())) {
+        Err(IntcodeError::OutputLimit { counter: 0, opcode: 104, executed: 6 }) => {}
+        r => panic!("{:?}", r),
+    }
+    assert_eq!(program.state(), State::Faulted);
+}
+
+#[test]
+fn test_overflow() {
+    let big = i64::MAX - 1;
+    let code = vec![1101, big, 3, 9, 1102, big, -2, 10, 99, 0, 0];
+
+    let mut program = Program::new(code.clone());
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::Overflow { counter: 0, opcode: 1101, operands: (a, 3) }) if a == big => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut program = Program::new(code.clone());
+    program.overflow = OverflowPolicy::Wrapping;
+    program.run(no_input, no_output).unwrap();
+    assert_eq!(program.memory.read(9), i64::MIN + 1);
+    assert_eq!(program.memory.read(10), 4);
+
+    let mut program = Program::new(code);
+    program.overflow = OverflowPolicy::Saturating;
+    program.run(no_input, no_output).unwrap();
+    assert_eq!(program.memory.read(9), i64::MAX);
+    assert_eq!(program.memory.read(10), i64::MIN);
+
+    // Relative base
+    let mut program = Program::new(vec![109, 1, 99]);
+    program.relative_base = i64::MAX;
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::Overflow { counter: 0, opcode: 109, operands: (i64::MAX, 1) }) => {}
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn test_cells() {
+    // Square a number twice, output it
+    let code = vec![3, 13, 2, 13, 13, 13, 2, 13, 13, 13, 4, 13, 99, 0];
+
+    let mut program = Program::new(code.clone());
+    program.push_input(1 << 20);
+    assert!(program.run_buffered().is_err());
+
+    let code_128: Vec<i128> = code.iter().map(|&v| v as i128).collect();
+    let mut program = Program::from_cells(code_128);
+    program.push_input(1 << 20);
+    assert_eq!(program.run_buffered().unwrap(), StopReason::Halted);
+    assert_eq!(program.take_output(), Some(1 << 80));
+
+    let code_big: Vec<BigInt> = code.iter().map(|&v| v.into()).collect();
+    let mut program = Program::from_cells(code_big);
+    program.push_input(BigInt::from(-1_000_000_000_000));
+    assert_eq!(program.run_buffered().unwrap(), StopReason::Halted);
+    assert_eq!(
+        program.take_output().unwrap().to_string(),
+        format!("1{}", "0".repeat(48)),
+    );
+
+    // Parse wide numbers
+    let program: Program<i128> = Program::read_cells(
+        &b"104,-100000000000000000000,99\n"[..],
+    ).unwrap();
+    assert_eq!(program.memory.read(1), -100_000_000_000_000_000_000);
+}
+
+#[test]
+fn test_errors() {
+    let mut program = Program::new(vec![1, 0, 0, 0, 42]);
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::InvalidOpcode { counter: 4, opcode: 42 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut program = Program::new(vec![1101, 1, 1, -3, 99]);
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::NegativeAddress { counter: 0, opcode: 1101, address: -3 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut program = Program::new(vec![11101, 1, 1, 0, 99]);
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::WriteToImmediate { counter: 0, opcode: 11101 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut program = Program::new(vec![1105, 1, -1]);
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::InvalidJumpTarget { counter: 0, opcode: 1105, target: -1 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut program = Program::new(vec![99, 3, 0, 99]);
+    program.counter = 1;
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::InputExhausted { counter: 1, opcode: 3 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    let mut program = Program::new(vec![104, 7, 99]);
+    match program.run(no_input, no_output) {
+        Err(IntcodeError::OutputRejected { counter: 0, opcode: 104, value: 7 }) => {}
+        r => panic!("{:?}", r),
+    }
+
+    match Program::from_reader(&b"1,2,x"[..]) {
+        Err(IntcodeError::ParseError {
+            line: 1,
+            column: 5,
+            kind: ParseErrorKind::InvalidCharacter(b'x'),
+        }) => {}
+        _ => panic!(),
     }
 }
 
-pub fn no_input() -> Res<i64> {
+pub fn no_input<C>() -> Res<C> {
     Err("No input available".into())
 }
 
-pub fn no_output(_: i64) -> Res<()> {
+pub fn no_output<C>(_: C) -> Res<()> {
     Err("No output possible".into())
 }
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::Cell;
+
+// Program as loaded, shared between machines
+pub type Image<C = i64> = Arc<[C]>;
+
+// Storage for a machine's memory. Addresses that were never written read as
+// 0; `len()` is one past the highest address that was loaded or written.
+pub trait Memory<C: Cell = i64> {
+    fn read(&self, addr: usize) -> C;
+    fn write(&mut self, addr: usize, value: C);
+    fn len(&self) -> usize;
+
+    // Forget everything at `len` and above, so it reads as 0 again. Does
+    // nothing if the memory is already shorter.
+    fn truncate(&mut self, len: usize);
+
+    // Replace the whole content with this image
+    fn load(&mut self, image: &Image<C>);
+
+    fn box_clone(&self) -> Box<dyn Memory<C>>;
+
+    // Short name of the backend, saved in snapshots
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    // An empty memory for the backend with this `name()`, if this type can
+    // be it. Snapshots are loaded through this.
+    fn from_name(_name: &str) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    // Ranges of addresses that may hold something other than 0, in order.
+    // Lets callers skip the gaps in memory that was written far away.
+    fn ranges(&self) -> Vec<Range<usize>> {
+        std::iter::once(0..self.len()).collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_vec(&self) -> Vec<C> {
+        (0..self.len()).map(|addr| self.read(addr)).collect()
+    }
+}
+
+impl<C: Cell> Clone for Box<dyn Memory<C>> {
+    fn clone(&self) -> Box<dyn Memory<C>> {
+        self.box_clone()
+    }
+}
+
+// Any backend, chosen at run time
+impl<C: Cell> Memory<C> for Box<dyn Memory<C>> {
+    fn read(&self, addr: usize) -> C {
+        (**self).read(addr)
+    }
+
+    fn write(&mut self, addr: usize, value: C) {
+        (**self).write(addr, value)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        (**self).truncate(len)
+    }
+
+    fn load(&mut self, image: &Image<C>) {
+        (**self).load(image)
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<C>> {
+        (**self).box_clone()
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn from_name(name: &str) -> Option<Box<dyn Memory<C>>> {
+        match name {
+            "dense" => Some(Box::new(DenseMemory::new())),
+            "paged" => Some(Box::new(PagedMemory::new())),
+            "sparse" => Some(Box::new(SparseMemory::new())),
+            _ => None,
+        }
+    }
+
+    fn ranges(&self) -> Vec<Range<usize>> {
+        (**self).ranges()
+    }
+
+    fn to_vec(&self) -> Vec<C> {
+        (**self).to_vec()
+    }
+}
+
+// Join ranges that touch or overlap, keeping them under `len`. They have to be
+// sorted by start.
+fn merge<I: Iterator<Item = Range<usize>>>(ranges: I, len: usize) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        let range = range.start..range.end.min(len);
+        if range.is_empty() {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+// One contiguous vector, grown as needed
+#[derive(Clone)]
+pub struct DenseMemory<C = i64>(Vec<C>);
+
+impl<C: Cell> DenseMemory<C> {
+    pub fn new() -> DenseMemory<C> {
+        DenseMemory(Vec::new())
+    }
+}
+
+impl<C: Cell> Default for DenseMemory<C> {
+    fn default() -> DenseMemory<C> {
+        DenseMemory::new()
+    }
+}
+
+impl<C: Cell> From<Vec<C>> for DenseMemory<C> {
+    fn from(cells: Vec<C>) -> DenseMemory<C> {
+        DenseMemory(cells)
+    }
+}
+
+impl<C: Cell> Memory<C> for DenseMemory<C> {
+    fn read(&self, addr: usize) -> C {
+        self.0.get(addr).cloned().unwrap_or_else(C::zero)
+    }
+
+    fn write(&mut self, addr: usize, value: C) {
+        if addr >= self.0.len() {
+            self.0.resize(addr + 1, C::zero());
+        }
+        self.0[addr] = value;
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+    }
+
+    fn load(&mut self, image: &Image<C>) {
+        self.0.clear();
+        self.0.extend_from_slice(image);
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<C>> {
+        Box::new(self.clone())
+    }
+
+    fn name(&self) -> &'static str {
+        "dense"
+    }
+
+    fn from_name(name: &str) -> Option<DenseMemory<C>> {
+        (name == "dense").then(DenseMemory::new)
+    }
+
+    fn to_vec(&self) -> Vec<C> {
+        self.0.clone()
+    }
+}
+
+pub const PAGE_SIZE: usize = 1024;
+
+// Fixed-size pages, only allocated when written to
+#[derive(Clone)]
+pub struct PagedMemory<C = i64> {
+    pages: Vec<Option<Box<[C]>>>,
+    len: usize,
+}
+
+impl<C: Cell> PagedMemory<C> {
+    pub fn new() -> PagedMemory<C> {
+        PagedMemory {
+            pages: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<C: Cell> Default for PagedMemory<C> {
+    fn default() -> PagedMemory<C> {
+        PagedMemory::new()
+    }
+}
+
+impl<C: Cell> Memory<C> for PagedMemory<C> {
+    fn read(&self, addr: usize) -> C {
+        match self.pages.get(addr / PAGE_SIZE) {
+            Some(Some(page)) => page[addr % PAGE_SIZE].clone(),
+            _ => C::zero(),
+        }
+    }
+
+    fn write(&mut self, addr: usize, value: C) {
+        let page_num = addr / PAGE_SIZE;
+        if page_num >= self.pages.len() {
+            self.pages.resize(page_num + 1, None);
+        }
+        let page = self.pages[page_num]
+            .get_or_insert_with(|| vec![C::zero(); PAGE_SIZE].into_boxed_slice());
+        page[addr % PAGE_SIZE] = value;
+        self.len = self.len.max(addr + 1);
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.pages.truncate(len.div_ceil(PAGE_SIZE));
+        if let Some(Some(page)) = self.pages.get_mut(len / PAGE_SIZE) {
+            for cell in &mut page[len % PAGE_SIZE..] {
+                *cell = C::zero();
+            }
+        }
+        self.len = len;
+    }
+
+    fn load(&mut self, image: &Image<C>) {
+        self.pages = image
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = vec![C::zero(); PAGE_SIZE];
+                page[..chunk.len()].clone_from_slice(chunk);
+                Some(page.into_boxed_slice())
+            })
+            .collect();
+        self.len = image.len();
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<C>> {
+        Box::new(self.clone())
+    }
+
+    fn name(&self) -> &'static str {
+        "paged"
+    }
+
+    fn from_name(name: &str) -> Option<PagedMemory<C>> {
+        (name == "paged").then(PagedMemory::new)
+    }
+
+    fn ranges(&self) -> Vec<Range<usize>> {
+        let pages = self.pages.iter().enumerate().filter(|(_, page)| page.is_some());
+        merge(pages.map(|(i, _)| i * PAGE_SIZE..(i + 1) * PAGE_SIZE), self.len)
+    }
+}
+
+// Only the cells that were set, in a hash map
+#[derive(Clone)]
+pub struct SparseMemory<C = i64> {
+    cells: HashMap<usize, C>,
+    len: usize,
+}
+
+impl<C: Cell> SparseMemory<C> {
+    pub fn new() -> SparseMemory<C> {
+        SparseMemory {
+            cells: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<C: Cell> Default for SparseMemory<C> {
+    fn default() -> SparseMemory<C> {
+        SparseMemory::new()
+    }
+}
+
+impl<C: Cell> Memory<C> for SparseMemory<C> {
+    fn read(&self, addr: usize) -> C {
+        self.cells.get(&addr).cloned().unwrap_or_else(C::zero)
+    }
+
+    fn write(&mut self, addr: usize, value: C) {
+        if value.is_zero() {
+            self.cells.remove(&addr);
+        } else {
+            self.cells.insert(addr, value);
+        }
+        self.len = self.len.max(addr + 1);
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.cells.retain(|&addr, _| addr < len);
+            self.len = len;
+        }
+    }
+
+    fn load(&mut self, image: &Image<C>) {
+        self.cells = image
+            .iter()
+            .enumerate()
+            .filter(|&(_, v)| !v.is_zero())
+            .map(|(addr, v)| (addr, v.clone()))
+            .collect();
+        self.len = image.len();
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory<C>> {
+        Box::new(self.clone())
+    }
+
+    fn name(&self) -> &'static str {
+        "sparse"
+    }
+
+    fn from_name(name: &str) -> Option<SparseMemory<C>> {
+        (name == "sparse").then(SparseMemory::new)
+    }
+
+    fn ranges(&self) -> Vec<Range<usize>> {
+        let mut addresses: Vec<usize> = self.cells.keys().cloned().collect();
+        addresses.sort_unstable();
+        merge(addresses.into_iter().map(|addr| addr..addr + 1), self.len)
+    }
+}
+
+#[test]
+fn test_backends() {
+    use crate::{Program, no_input, no_output};
+
+    let backends: Vec<Box<dyn Memory>> = vec![
+        Box::new(DenseMemory::new()),
+        Box::new(PagedMemory::new()),
+        Box::new(SparseMemory::new()),
+    ];
+    for backend in backends {
+        // Grows memory by writing at 1500, across a page boundary
+        let mut program = Program::with_memory(
+            vec![1101, 5, 6, 1500, 4, 1500, 99],
+            backend,
+        );
+        let mut output = Vec::new();
+        program.run(no_input, |i| { output.push(i); Ok(()) }).unwrap();
+        assert_eq!(output, vec![11]);
+        assert_eq!(program.memory.len(), 1501);
+        assert_eq!(program.memory.read(1500), 11);
+        assert_eq!(program.memory.read(1499), 0);
+        assert_eq!(program.memory.read(100_000), 0);
+        assert_eq!(&program.memory.to_vec()[..7], &[1101, 5, 6, 1500, 4, 1500, 99]);
+
+        program.reset();
+        assert_eq!(program.memory.to_vec(), vec![1101, 5, 6, 1500, 4, 1500, 99]);
+
+        // Shrinking forgets what was written past the end
+        program.memory.write(1030, 7);
+        program.memory.truncate(1025);
+        assert_eq!(program.memory.len(), 1025);
+        program.memory.write(1040, 1);
+        assert_eq!(program.memory.read(1030), 0);
+        program.memory.truncate(5);
+        assert_eq!(program.memory.to_vec(), vec![1101, 5, 6, 1500, 4]);
+    }
+
+    // A write far away doesn't allocate everything in between
+    let backends: Vec<Box<dyn Memory>> = vec![
+        Box::new(PagedMemory::new()),
+        Box::new(SparseMemory::new()),
+    ];
+    for backend in backends {
+        let mut program = Program::with_memory(vec![21101, 0, 1, 1 << 31, 99], backend);
+        program.relative_base = 1 << 30;
+        program.run(no_input, no_output).unwrap();
+        assert_eq!(program.memory.len(), (1 << 31) + (1 << 30) + 1);
+        assert_eq!(program.memory.read((1 << 31) + (1 << 30)), 1);
+    }
+}
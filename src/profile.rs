@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::disasm::disassemble_one;
+use crate::trace::{install, install_from_env};
+use crate::{Cell, Memory, Opcode, Program, TraceEvent, Tracer};
+
+// Counts instructions per address, opcode and parameter mode
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    pub instructions: u64,
+    addresses: Vec<u64>,
+    opcodes: BTreeMap<Opcode, u64>,
+    // Position, immediate, relative
+    modes: [u64; 3],
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Default::default()
+    }
+
+    // Add a new profiler to the program, returning a handle to it
+    pub fn install<C: Cell, M: Memory<C>>(program: &mut Program<C, M>) -> Arc<Mutex<Profiler>> {
+        install(program)
+    }
+
+    // Number of times the instruction at this address ran
+    pub fn count(&self, address: usize) -> u64 {
+        self.addresses.get(address).cloned().unwrap_or(0)
+    }
+
+    // Number of times this opcode ran, whatever the modes
+    pub fn opcode_count(&self, opcode: Opcode) -> u64 {
+        self.opcodes.get(&opcode).cloned().unwrap_or(0)
+    }
+
+    pub fn mode_counts(&self) -> [u64; 3] {
+        self.modes
+    }
+
+    // Summary and the `top` most executed addresses, disassembled from memory
+    pub fn report<C: Cell, M: Memory<C> + ?Sized>(&self, memory: &M, top: usize) -> String {
+        let percent = |n: u64| 100.0 * n as f64 / self.instructions.max(1) as f64;
+        let mut report = String::new();
+        let _ = writeln!(report, "Instructions: {}", self.instructions);
+
+        let _ = writeln!(report, "Opcodes:");
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (&instr, &count) in opcodes {
+            let _ = writeln!(
+                report,
+                "  {:<4} {:>12} {:>6.2}%",
+                instr.mnemonic(), count, percent(count),
+            );
+        }
+
+        let _ = writeln!(
+            report,
+            "Modes: position {}, immediate {}, relative {}",
+            self.modes[0], self.modes[1], self.modes[2],
+        );
+
+        let _ = writeln!(report, "Hotspots:");
+        let mut hotspots: Vec<(usize, u64)> = self
+            .addresses
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(addr, &count)| (addr, count))
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for &(addr, count) in hotspots.iter().take(top) {
+            let _ = writeln!(
+                report,
+                "  {:>12} {:>6.2}%  {}",
+                count, percent(count), disassemble_one(memory, addr),
+            );
+        }
+        report
+    }
+}
+
+impl<C: Cell> Tracer<C> for Profiler {
+    fn event(&mut self, event: &TraceEvent<C>) {
+        if let TraceEvent::Instruction { counter, opcode, instr } = event {
+            self.instructions += 1;
+            if *counter >= self.addresses.len() {
+                self.addresses.resize(counter + 1, 0);
+            }
+            self.addresses[*counter] += 1;
+            *self.opcodes.entry(*instr).or_insert(0) += 1;
+
+            let nb_params = instr.parameter_count();
+            let mut modes = opcode.to_i64().unwrap_or(0) / 100;
+            for _ in 0..nb_params {
+                if let Some(count) = self.modes.get_mut((modes % 10) as usize) {
+                    *count += 1;
+                }
+                modes /= 10;
+            }
+        }
+    }
+}
+
+// Environment variable that turns on profiling in the day binaries
+pub const PROFILE_ENV: &str = "INTCODE_PROFILE";
+
+// Install a profiler if `INTCODE_PROFILE` is set
+pub fn profiler_from_env<C, M>(program: &mut Program<C, M>) -> Option<Arc<Mutex<Profiler>>>
+where
+    C: Cell,
+    M: Memory<C>,
+{
+    install_from_env(program, PROFILE_ENV)
+}
+
+#[test]
+fn test_profiler() {
+    // Count down from 3, outputting each value
+    let mut program = Program::new(vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3]);
+    let profiler = Profiler::install(&mut program);
+    let mut output = Vec::new();
+    program.run(crate::no_input, |v| { output.push(v); Ok(()) }).unwrap();
+    assert_eq!(output, vec![3, 2, 1]);
+
+    let profiler = profiler.lock().unwrap();
+    assert_eq!(profiler.instructions, 10);
+    assert_eq!((profiler.count(0), profiler.count(2), profiler.count(9)), (3, 3, 1));
+    assert_eq!(profiler.opcode_count(Opcode::JumpIfTrue), 3);
+    assert_eq!(profiler.mode_counts(), [12, 6, 0]);
+
+    let report = profiler.report(&program.memory, 2);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "Instructions: 10");
+    assert_eq!(lines[1], "Opcodes:");
+    assert_eq!(lines[6], "Modes: position 12, immediate 6, relative 0");
+    assert_eq!(
+        &lines[7..],
+        &[
+            "Hotspots:",
+            "             3  30.00%  0000: OUT [10]",
+            "             3  30.00%  0002: ADD [10], #-1, [10]",
+        ],
+    );
+}
@@ -0,0 +1,289 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    read_program, Cell, HaltReason, IntcodeError, Memory, OverflowPolicy, Program, State,
+};
+
+// Saved machine state, as text. Version 1 is one `key value` pair per line,
+// in this order:
+//
+//   intcode-snapshot 1
+//   counter 12
+//   relative_base 0
+//   state Running                 (Ready, Running, AwaitingInput, Halted, Faulted)
+//   halt_reason none              (none, Explicit, EndOfMemory, Fault)
+//   instructions 1234             (executed so far)
+//   outputs 56                    (output so far, for the output limit)
+//   strict false
+//   overflow Checked              (Checked, Wrapping, Saturating)
+//   input 1,2                     (queued input, may be empty)
+//   output                        (buffered output, may be empty)
+//   backend dense                 (dense, paged, sparse)
+//   image 3,9,8,9,10,9,4,9,99,-1,8   (memory the program was created with)
+//   len 11                        (current length of memory)
+//   memory 9:8 1000:1,2           (cells that differ from the image, as runs)
+//
+// Lists are comma-separated, like the program files. Each run is a start
+// address, a colon and the values from there; runs are separated by spaces.
+// Limits, breakpoints, tracers and the undo log are not saved.
+pub const SNAPSHOT_VERSION: u32 = 1;
+const MAGIC: &str = "intcode-snapshot";
+
+fn join<'a, C: Cell, I: Iterator<Item = &'a C>>(values: I) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn parse_state(value: &str) -> Option<State> {
+    match value {
+        "Ready" => Some(State::Ready),
+        "Running" => Some(State::Running),
+        "AwaitingInput" => Some(State::AwaitingInput),
+        "Halted" => Some(State::Halted),
+        "Faulted" => Some(State::Faulted),
+        _ => None,
+    }
+}
+
+fn parse_halt_reason(value: &str) -> Option<Option<HaltReason>> {
+    match value {
+        "none" => Some(None),
+        "Explicit" => Some(Some(HaltReason::Explicit)),
+        "EndOfMemory" => Some(Some(HaltReason::EndOfMemory)),
+        "Fault" => Some(Some(HaltReason::Fault)),
+        _ => None,
+    }
+}
+
+fn parse_overflow(value: &str) -> Option<OverflowPolicy> {
+    match value {
+        "Checked" => Some(OverflowPolicy::Checked),
+        "Wrapping" => Some(OverflowPolicy::Wrapping),
+        "Saturating" => Some(OverflowPolicy::Saturating),
+        _ => None,
+    }
+}
+
+fn parse_runs<C: Cell>(value: &str) -> Option<Vec<(usize, Vec<C>)>> {
+    value
+        .split_whitespace()
+        .map(|run| {
+            let (start, values) = run.split_once(':')?;
+            Some((start.parse().ok()?, parse_list(values)?))
+        })
+        .collect()
+}
+
+fn parse_list<C: Cell>(value: &str) -> Option<Vec<C>> {
+    if value.is_empty() {
+        Some(Vec::new())
+    } else {
+        read_program(value.as_bytes()).ok()
+    }
+}
+
+// Reads the lines one by one, checking their keys
+struct Reader<R> {
+    input: R,
+    line: usize,
+}
+
+impl<R: BufRead> Reader<R> {
+    fn field<C, T, F>(&mut self, key: &'static str, parse: F) -> Result<T, IntcodeError<C>>
+    where
+        F: FnOnce(&str) -> Option<T>,
+    {
+        self.line += 1;
+        let line = self.line;
+        let error = |message| IntcodeError::InvalidSnapshot { line, message };
+        let mut text = String::new();
+        if self.input.read_line(&mut text)? == 0 {
+            return Err(error("unexpected end of file"));
+        }
+        let text = text.trim_end_matches(['\n', '\r']);
+        let value = match text.split_once(' ') {
+            Some((k, value)) if k == key => value,
+            None if text == key => "",
+            _ => return Err(error(key)),
+        };
+        parse(value).ok_or_else(|| error(key))
+    }
+}
+
+impl<C: Cell, M: Memory<C>> Program<C, M> {
+    pub fn save<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        writeln!(out, "{} {}", MAGIC, SNAPSHOT_VERSION)?;
+        writeln!(out, "counter {}", self.counter)?;
+        writeln!(out, "relative_base {}", self.relative_base)?;
+        writeln!(out, "state {:?}", self.state)?;
+        match self.halt_reason {
+            Some(reason) => writeln!(out, "halt_reason {:?}", reason)?,
+            None => writeln!(out, "halt_reason none")?,
+        }
+        writeln!(out, "instructions {}", self.instructions)?;
+        writeln!(out, "outputs {}", self.outputs)?;
+        writeln!(out, "strict {}", self.strict)?;
+        writeln!(out, "overflow {:?}", self.overflow)?;
+        writeln!(out, "input {}", join(self.input.iter()))?;
+        writeln!(out, "output {}", join(self.output.iter()))?;
+        writeln!(out, "backend {}", self.memory.name())?;
+        writeln!(out, "image {}", join(self.image.iter()))?;
+        writeln!(out, "len {}", self.memory.len())?;
+        let runs: Vec<String> = self
+            .changes()
+            .iter()
+            .map(|(start, values)| format!("{}:{}", start, join(values.iter())))
+            .collect();
+        writeln!(out, "memory {}", runs.join(" "))?;
+        Ok(())
+    }
+
+    // Runs of cells that differ from the image
+    fn changes(&self) -> Vec<(usize, Vec<C>)> {
+        let mut runs: Vec<(usize, Vec<C>)> = Vec::new();
+        for addr in self.memory.ranges().into_iter().flatten() {
+            let value = self.memory.read(addr);
+            if value == self.image.get(addr).cloned().unwrap_or_else(C::zero) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((start, values)) if *start + values.len() == addr => values.push(value),
+                _ => runs.push((addr, vec![value])),
+            }
+        }
+        runs
+    }
+
+    // Load a snapshot written by `save()`. The backend has to be one `M` can
+    // be, e.g. `Box<dyn Memory<C>>` for any of them. Errors give the line
+    // number and the field that couldn't be read.
+    pub fn load<R: BufRead>(input: R) -> Result<Program<C, M>, IntcodeError<C>> {
+        let mut reader = Reader { input, line: 0 };
+        reader.field(MAGIC, |v| v.parse::<u32>().ok().filter(|&v| v == SNAPSHOT_VERSION))?;
+        let counter = reader.field("counter", |v| v.parse().ok())?;
+        let relative_base = reader.field("relative_base", |v| {
+            parse_list::<C>(v).filter(|l| l.len() == 1).map(|mut l| l.remove(0))
+        })?;
+        let state = reader.field("state", parse_state)?;
+        let halt_reason = reader.field("halt_reason", parse_halt_reason)?;
+        let instructions = reader.field("instructions", |v| v.parse().ok())?;
+        let outputs = reader.field("outputs", |v| v.parse().ok())?;
+        let strict = reader.field("strict", |v| v.parse().ok())?;
+        let overflow = reader.field("overflow", parse_overflow)?;
+        let input = reader.field("input", parse_list)?;
+        let output = reader.field("output", parse_list)?;
+
+        let backend = reader.field("backend", M::from_name)?;
+        let image = reader.field("image", parse_list)?;
+        let len: usize = reader.field("len", |v| v.parse().ok())?;
+        let runs = reader.field("memory", parse_runs)?;
+
+        let mut program = Program::with_memory(image, backend);
+        for (start, values) in runs {
+            for (i, value) in values.into_iter().enumerate() {
+                program.memory.write(start + i, value);
+            }
+        }
+        // Memory may have grown by writing zeros
+        if len > program.memory.len() {
+            program.memory.write(len - 1, C::zero());
+        }
+        program.memory.truncate(len);
+        program.counter = counter;
+        program.relative_base = relative_base;
+        program.state = state;
+        program.halt_reason = halt_reason;
+        program.instructions = instructions;
+        program.outputs = outputs;
+        program.strict = strict;
+        program.overflow = overflow;
+        program.input = input.into();
+        program.output = output.into();
+        Ok(program)
+    }
+}
+
+#[test]
+fn test_snapshot() {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use crate::StopReason;
+
+    // Run the day 11 robot for a while, on black panels
+    let file = BufReader::new(File::open("inputs/day11.txt").unwrap());
+    let mut program = Program::from_reader(file).unwrap();
+    for _ in 0..50 {
+        program.push_input(0);
+        program.run_buffered().unwrap();
+    }
+    program.push_input(1);
+
+    let mut saved = Vec::new();
+    program.save(&mut saved).unwrap();
+    let text = String::from_utf8(saved.clone()).unwrap();
+    assert!(text.starts_with("intcode-snapshot 1\ncounter "));
+    assert!(text.contains("\nstate AwaitingInput\nhalt_reason none\n"));
+    assert!(text.contains("\ninput 1\n"));
+
+    // Both machines go on the same way
+    let mut loaded: Program = Program::load(&saved[..]).unwrap();
+    assert_eq!(loaded.memory.to_vec(), program.memory.to_vec());
+    let outputs = |p: &mut Program| p.drain_output().collect::<Vec<_>>();
+    assert_eq!(outputs(&mut loaded), outputs(&mut program));
+    for i in 0..50 {
+        assert_eq!(loaded.run_buffered().unwrap(), program.run_buffered().unwrap());
+        assert_eq!(outputs(&mut loaded), outputs(&mut program));
+        loaded.push_input(i % 2);
+        program.push_input(i % 2);
+    }
+    assert_eq!(loaded.instructions(), program.instructions());
+
+    // Reset goes back to the original program
+    loaded.reset();
+    program.reset();
+    assert_eq!(loaded.memory.to_vec(), program.memory.to_vec());
+    assert_eq!(loaded.resume().unwrap(), StopReason::NeedsInput);
+
+    // Errors point at the line
+    let bad = text.replace("state AwaitingInput", "state Sleeping");
+    match Program::<i64>::load(bad.as_bytes()) {
+        Err(IntcodeError::InvalidSnapshot { line: 4, message: "state" }) => {}
+        r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+    }
+    match Program::<i64>::load(&b"intcode-snapshot 2\n"[..]) {
+        Err(IntcodeError::InvalidSnapshot { line: 1, .. }) => {}
+        r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_snapshot_memory() {
+    use crate::PagedMemory;
+
+    // Only the changes are saved, not the gap before a far write
+    let memory = vec![1101, 2, 3, 7, 99];
+    let mut program: Program<i64, _> = Program::with_memory(memory, PagedMemory::new());
+    program.run(crate::no_input, crate::no_output).unwrap();
+    program.memory.write(3_000_000_000, 5);
+    program.memory.write(3_000_000_001, -6);
+    let mut saved = Vec::new();
+    program.save(&mut saved).unwrap();
+    let text = String::from_utf8(saved.clone()).unwrap();
+    assert!(text.ends_with(
+        "backend paged\nimage 1101,2,3,7,99\nlen 3000000002\nmemory 7:5 3000000000:5,-6\n"
+    ));
+
+    let loaded: Program<i64, PagedMemory> = Program::load(&saved[..]).unwrap();
+    assert_eq!(loaded.memory.len(), 3_000_000_002);
+    assert_eq!(loaded.memory.read(7), 5);
+    assert_eq!(loaded.memory.read(3_000_000_001), -6);
+
+    // The backend has to match, unless it is picked at run time
+    match Program::<i64>::load(&saved[..]) {
+        Err(IntcodeError::InvalidSnapshot { line: 12, message: "backend" }) => {}
+        r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+    }
+    let loaded: Program<i64, Box<dyn Memory>> = Program::load(&saved[..]).unwrap();
+    assert_eq!(loaded.memory.name(), "paged");
+    assert_eq!(loaded.memory.read(3_000_000_000), 5);
+}
@@ -0,0 +1,393 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::asm::SourceMap;
+use crate::{Cell, Memory, Opcode, Parameter, Program, Res};
+
+// What happened while executing an instruction, in order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceEvent<C = i64> {
+    // Start of an instruction; `opcode` is the raw value, with the modes
+    Instruction { counter: usize, opcode: C, instr: Opcode },
+    // An operand was read. `address` is where from, if not immediate.
+    Operand { parameter: Parameter<C>, address: Option<usize>, value: C },
+    Write { address: usize, old: C, new: C },
+    Input(C),
+    Output(C),
+    RelativeBase { old: C, new: C },
+}
+
+// Gets every event from a machine. Events are only built when a tracer is
+// installed.
+pub trait Tracer<C: Cell = i64> {
+    fn event(&mut self, event: &TraceEvent<C>);
+}
+
+// Shared, so the caller can keep a handle to it, and `Send` so the program
+// can still go to another thread
+pub type SharedTracer<C = i64> = Arc<Mutex<dyn Tracer<C> + Send>>;
+
+// Add a new tracer to the program, alongside any others, and return a handle
+// to it. Clones of the program share it, so it adds up all their runs.
+pub(crate) fn install<C, M, T>(program: &mut Program<C, M>) -> Arc<Mutex<T>>
+where
+    C: Cell,
+    M: Memory<C>,
+    T: Tracer<C> + Default + Send + 'static,
+{
+    let tracer = Arc::new(Mutex::new(T::default()));
+    program.add_tracer(tracer.clone());
+    tracer
+}
+
+// Same, only if this environment variable is set
+pub(crate) fn install_from_env<C, M, T>(
+    program: &mut Program<C, M>,
+    var: &str,
+) -> Option<Arc<Mutex<T>>>
+where
+    C: Cell,
+    M: Memory<C>,
+    T: Tracer<C> + Default + Send + 'static,
+{
+    env::var_os(var).map(|_| install(program))
+}
+
+// Keeps all events, for tests
+#[derive(Clone, Debug, Default)]
+pub struct CollectingTracer<C = i64> {
+    pub events: Vec<TraceEvent<C>>,
+}
+
+impl<C: Cell> CollectingTracer<C> {
+    pub fn new() -> CollectingTracer<C> {
+        CollectingTracer { events: Vec::new() }
+    }
+}
+
+impl<C: Cell> Tracer<C> for CollectingTracer<C> {
+    fn event(&mut self, event: &TraceEvent<C>) {
+        self.events.push(event.clone());
+    }
+}
+
+// Writes a line per event. Write errors are ignored.
+pub struct PrintTracer<W> {
+    out: W,
+    // To show where instructions come from in the source
+    source_map: Option<SourceMap>,
+}
+
+impl<W: Write> PrintTracer<W> {
+    pub fn new(out: W) -> PrintTracer<W> {
+        PrintTracer { out, source_map: None }
+    }
+
+    pub fn with_source_map(out: W, source_map: SourceMap) -> PrintTracer<W> {
+        PrintTracer { out, source_map: Some(source_map) }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<C: Cell, W: Write> Tracer<C> for PrintTracer<W> {
+    fn event(&mut self, event: &TraceEvent<C>) {
+        let _ = match event {
+            TraceEvent::Instruction { counter, opcode, instr } => {
+                match self.source_map.as_ref().and_then(|map| map.lookup(*counter)) {
+                    Some(location) => writeln!(
+                        self.out,
+                        "{:04}: {} ({}) at {}",
+                        counter, opcode, instr, location,
+                    ),
+                    None => writeln!(self.out, "{:04}: {} ({})", counter, opcode, instr),
+                }
+            }
+            TraceEvent::Operand { parameter, address, value } => match (parameter, address) {
+                // Show where relative operands point to
+                (Parameter::Relative(_), Some(addr)) => {
+                    writeln!(self.out, "    {} [{}] = {}", parameter, addr, value)
+                }
+                _ => writeln!(self.out, "    {} = {}", parameter, value),
+            },
+            TraceEvent::Write { address, old, new } => {
+                writeln!(self.out, "    [{}] {} -> {}", address, old, new)
+            }
+            TraceEvent::Input(v) => writeln!(self.out, "    input {}", v),
+            TraceEvent::Output(v) => writeln!(self.out, "    output {}", v),
+            TraceEvent::RelativeBase { old, new } => {
+                writeln!(self.out, "    relative base {} -> {}", old, new)
+            }
+        };
+    }
+}
+
+// Writes one JSON object per instruction, one per line:
+// {"step":0,"counter":4,"opcode":1002,"modes":[0,1,0],
+//  "operands":[{"mode":0,"raw":9,"address":9,"value":8},...],
+//  "writes":[{"address":9,"old":8,"new":1}],"input":null,"output":null,
+//  "relative_base":null}
+// The last record is written when the tracer is dropped. `begin_run()` writes
+// a {"run":"name"} line and starts counting steps from 0 again, to tell runs
+// apart in one file.
+pub struct JsonTracer<W: Write> {
+    out: W,
+    step: u64,
+    // Record for the current instruction, without its closing brace
+    record: Option<String>,
+    operands: Vec<String>,
+    writes: Vec<String>,
+    input: Option<String>,
+    output: Option<String>,
+    relative_base: Option<String>,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(out: W) -> JsonTracer<W> {
+        JsonTracer {
+            out,
+            step: 0,
+            record: None,
+            operands: Vec::new(),
+            writes: Vec::new(),
+            input: None,
+            output: None,
+            relative_base: None,
+        }
+    }
+
+    pub fn begin_run(&mut self, name: &str) {
+        self.flush();
+        let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(self.out, "{{\"run\":\"{}\"}}", name);
+        self.step = 0;
+    }
+
+    // Write the pending record, if any
+    pub fn flush(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            let null = || "null".to_owned();
+            let _ = write!(
+                record,
+                ",\"operands\":[{}],\"writes\":[{}],\"input\":{},\"output\":{},\
+                 \"relative_base\":{}}}",
+                self.operands.join(","),
+                self.writes.join(","),
+                self.input.take().unwrap_or_else(null),
+                self.output.take().unwrap_or_else(null),
+                self.relative_base.take().unwrap_or_else(null),
+            );
+            self.operands.clear();
+            self.writes.clear();
+            let _ = writeln!(self.out, "{}", record);
+            let _ = self.out.flush();
+        }
+    }
+}
+
+impl<W: Write> Drop for JsonTracer<W> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl<C: Cell, W: Write> Tracer<C> for JsonTracer<W> {
+    fn event(&mut self, event: &TraceEvent<C>) {
+        match event {
+            TraceEvent::Instruction { counter, opcode, instr } => {
+                self.flush();
+                let nb_params = instr.parameter_count();
+                let mut modes = opcode.to_i64().unwrap_or(0) / 100;
+                let modes: Vec<String> = (0..nb_params)
+                    .map(|_| {
+                        let mode = modes % 10;
+                        modes /= 10;
+                        mode.to_string()
+                    })
+                    .collect();
+                self.record = Some(format!(
+                    "{{\"step\":{},\"counter\":{},\"opcode\":{},\"modes\":[{}]",
+                    self.step,
+                    counter,
+                    opcode,
+                    modes.join(","),
+                ));
+                self.step += 1;
+            }
+            TraceEvent::Operand { parameter, address, value } => {
+                let (mode, raw) = (parameter.mode(), parameter.value());
+                let address = address.map_or_else(|| "null".to_owned(), |a| a.to_string());
+                self.operands.push(format!(
+                    "{{\"mode\":{},\"raw\":{},\"address\":{},\"value\":{}}}",
+                    mode, raw, address, value,
+                ));
+            }
+            TraceEvent::Write { address, old, new } => {
+                self.writes.push(format!(
+                    "{{\"address\":{},\"old\":{},\"new\":{}}}",
+                    address, old, new,
+                ));
+            }
+            TraceEvent::Input(v) => self.input = Some(v.to_string()),
+            TraceEvent::Output(v) => self.output = Some(v.to_string()),
+            TraceEvent::RelativeBase { old, new } => {
+                self.relative_base = Some(format!("{{\"old\":{},\"new\":{}}}", old, new));
+            }
+        }
+    }
+}
+
+// Environment variable naming the file to write a JSON trace to
+pub const TRACE_ENV: &str = "INTCODE_TRACE";
+
+pub type FileJsonTracer = Arc<Mutex<JsonTracer<BufWriter<File>>>>;
+
+// A JSON tracer writing to the file named by `INTCODE_TRACE`, if it is set
+pub fn json_tracer_from_env() -> Res<Option<FileJsonTracer>> {
+    match env::var_os(TRACE_ENV) {
+        Some(path) => {
+            let file = BufWriter::new(File::create(path)?);
+            Ok(Some(Arc::new(Mutex::new(JsonTracer::new(file)))))
+        }
+        None => Ok(None),
+    }
+}
+
+#[test]
+fn test_tracers() {
+    use crate::Program;
+
+    // Read a number, add the relative base to it, output it
+    let mut program = Program::new(vec![109, 10, 203, 1, 22201, 0, 1, 1, 204, 1, 99]);
+    let collect = Arc::new(Mutex::new(CollectingTracer::new()));
+    program.set_tracer(Some(collect.clone()));
+    let mut output = Vec::new();
+    program.run(|| Ok(5), |v| { output.push(v); Ok(()) }).unwrap();
+    assert_eq!(output, vec![104]);
+
+    let events = &collect.lock().unwrap().events;
+    assert_eq!(events.len(), 14);
+    assert_eq!(
+        &events[..8],
+        &[
+            TraceEvent::Instruction { counter: 0, opcode: 109, instr: Opcode::AdjustRelativeBase },
+            TraceEvent::Operand {
+                parameter: Parameter::Immediate(10),
+                address: None,
+                value: 10,
+            },
+            TraceEvent::RelativeBase { old: 0, new: 10 },
+            TraceEvent::Instruction { counter: 2, opcode: 203, instr: Opcode::Input },
+            TraceEvent::Input(5),
+            TraceEvent::Write { address: 11, old: 0, new: 5 },
+            TraceEvent::Instruction { counter: 4, opcode: 22201, instr: Opcode::Add },
+            TraceEvent::Operand {
+                parameter: Parameter::Relative(0),
+                address: Some(10),
+                value: 99,
+            },
+        ],
+    );
+
+    program.reset();
+    let print = Arc::new(Mutex::new(PrintTracer::new(Vec::new())));
+    program.set_tracer(Some(print.clone()));
+    program.run(|| Ok(5), |_| Ok(())).unwrap();
+    program.set_tracer(None);
+    let text = String::from_utf8(print.lock().unwrap().out.clone()).unwrap();
+    assert_eq!(
+        text.lines().take(10).collect::<Vec<_>>(),
+        vec![
+            "0000: 109 (ARB)",
+            "    #10 = 10",
+            "    relative base 0 -> 10",
+            "0002: 203 (IN)",
+            "    input 5",
+            "    [11] 0 -> 5",
+            "0004: 22201 (ADD)",
+            "    [r+0] [10] = 99",
+            "    [r+1] [11] = 5",
+            "    [11] 5 -> 104",
+        ],
+    );
+
+    // Instructions from assembly show where they come from
+    let source = "start: in [value]\nout [value]\nhlt\nvalue: db 0\n";
+    let (memory, map) = crate::asm::assemble_with_map(source, "echo.s").unwrap();
+    let mut echo = Program::new(memory);
+    let print_map = Arc::new(Mutex::new(PrintTracer::with_source_map(Vec::new(), map)));
+    echo.set_tracer(Some(print_map.clone()));
+    echo.run(|| Ok(5), |_| Ok(())).unwrap();
+    let lines = String::from_utf8(print_map.lock().unwrap().out.clone()).unwrap();
+    assert_eq!(
+        lines.lines().filter(|l| !l.starts_with(' ')).collect::<Vec<_>>(),
+        vec![
+            "0000: 3 (IN) at echo.s:1 (start)",
+            "0002: 4 (OUT) at echo.s:2 (start+2)",
+            "0004: 99 (HLT) at echo.s:3 (start+4)",
+        ],
+    );
+
+    // No tracer, no events
+    program.reset();
+    program.run(|| Ok(5), |_| Ok(())).unwrap();
+    assert_eq!(print.lock().unwrap().out.len(), text.len());
+}
+
+#[test]
+fn test_json_tracer() {
+    use crate::Program;
+
+    let mut program = Program::new(vec![3, 9, 1008, 9, 8, 9, 204, 9, 99, 0]);
+    let out = Arc::new(Mutex::new(Vec::new()));
+
+    // Write to a shared buffer, so we can read it after the tracer is dropped
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let tracer = Arc::new(Mutex::new(JsonTracer::new(Shared(out.clone()))));
+    program.set_tracer(Some(tracer.clone()));
+    program.run(|| Ok(8), |_| Ok(())).unwrap();
+
+    // A new run starts counting from 0 again
+    program.reset();
+    tracer.lock().unwrap().begin_run("again");
+    program.run(|| Ok(7), |_| Ok(())).unwrap();
+    program.set_tracer(None);
+    drop(tracer);
+
+    let text = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines[..4],
+        [
+            "{\"step\":0,\"counter\":0,\"opcode\":3,\"modes\":[0],\"operands\":[],\
+             \"writes\":[{\"address\":9,\"old\":0,\"new\":8}],\"input\":8,\"output\":null,\
+             \"relative_base\":null}",
+            "{\"step\":1,\"counter\":2,\"opcode\":1008,\"modes\":[0,1,0],\
+             \"operands\":[{\"mode\":0,\"raw\":9,\"address\":9,\"value\":8},\
+             {\"mode\":1,\"raw\":8,\"address\":null,\"value\":8}],\
+             \"writes\":[{\"address\":9,\"old\":8,\"new\":1}],\"input\":null,\"output\":null,\
+             \"relative_base\":null}",
+            "{\"step\":2,\"counter\":6,\"opcode\":204,\"modes\":[2],\
+             \"operands\":[{\"mode\":2,\"raw\":9,\"address\":9,\"value\":1}],\
+             \"writes\":[],\"input\":null,\"output\":1,\"relative_base\":null}",
+            "{\"step\":3,\"counter\":8,\"opcode\":99,\"modes\":[],\"operands\":[],\
+             \"writes\":[],\"input\":null,\"output\":null,\"relative_base\":null}",
+        ],
+    );
+    assert_eq!(lines[4], "{\"run\":\"again\"}");
+    assert!(lines[5].starts_with("{\"step\":0,\"counter\":0,\"opcode\":3,"));
+    assert_eq!(lines.len(), 9);
+}